@@ -0,0 +1,28 @@
+-- Your SQL goes here
+CREATE TABLE autoresponses (
+    autoresponse_id INTEGER PRIMARY KEY ASC NOT NULL,
+    pattern TEXT NOT NULL,
+    match_kind TEXT NOT NULL,
+    case_sensitive BOOLEAN NOT NULL DEFAULT 1,
+    response TEXT NOT NULL,
+    moderator_only BOOLEAN NOT NULL DEFAULT 0,
+    enabled BOOLEAN NOT NULL DEFAULT 1
+);
+
+-- Seed the responses that used to be hardcoded in the autoresponder, in the order they were checked.
+INSERT INTO autoresponses (pattern, match_kind, case_sensitive, response, moderator_only) VALUES
+    ('wkjfneasdf', 'exact', 1, 'That''s not a word, but rather an oddly specific keyboard mash. Good job!', 0),
+    ('test', 'exact', 1, 'turing', 0),
+    ('f ', 'prefix', 0, ':regional_indicator_f:', 0),
+    ('f', 'exact', 0, ':regional_indicator_f:', 0),
+    ('^^ ', 'prefix', 1, '^u^', 0),
+    ('^^', 'exact', 1, '^u^', 0),
+    ('\o/', 'prefix', 1, 'https://imgur.com/qJYq4Xn', 0),
+    ('crab', 'prefix', 0, 'https://tenor.com/view/crab-safe-dance-gif-13211112', 0),
+    ('sock ruse', 'prefix', 0, 'it was a DISTACTION', 0),
+    ('what time is it?', 'prefix', 0, 'https://tenor.com/view/pizza-time-its-delivery-gif-13167414', 0),
+    ('radio lags', 'contains', 0, '<@84774207140945920>{split}https://media.discordapp.net/attachments/551868267099193374/768520702402887710/unknown.png', 0),
+    ('radio is lagging', 'contains', 0, '<@84774207140945920>{split}https://media.discordapp.net/attachments/551868267099193374/768520702402887710/unknown.png', 0),
+    ('chirp', 'prefix', 1, 'chirp chirp!', 1),
+    ('bark', 'prefix', 1, 'bork', 1),
+    ('bear', 'prefix', 1, ':bear:', 1);
@@ -0,0 +1,15 @@
+-- This file should undo anything in `up.sql`
+-- SQLite before 3.35 can't drop columns, so the table is rebuilt without them
+CREATE TABLE autoresponses_new (
+    autoresponse_id INTEGER PRIMARY KEY ASC NOT NULL,
+    pattern TEXT NOT NULL,
+    match_kind TEXT NOT NULL,
+    case_sensitive BOOLEAN NOT NULL DEFAULT 1,
+    response TEXT NOT NULL,
+    moderator_only BOOLEAN NOT NULL DEFAULT 0,
+    enabled BOOLEAN NOT NULL DEFAULT 1
+);
+INSERT INTO autoresponses_new (autoresponse_id, pattern, match_kind, case_sensitive, response, moderator_only, enabled)
+    SELECT autoresponse_id, pattern, match_kind, case_sensitive, response, moderator_only, enabled FROM autoresponses;
+DROP TABLE autoresponses;
+ALTER TABLE autoresponses_new RENAME TO autoresponses;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE autoresponses ADD COLUMN channel_cooldown_secs INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE autoresponses ADD COLUMN user_cooldown_secs INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE autoresponses ADD COLUMN chance_percent INTEGER NOT NULL DEFAULT 100;
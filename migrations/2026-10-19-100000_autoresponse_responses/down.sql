@@ -0,0 +1,37 @@
+-- This file should undo anything in `up.sql`
+-- SQLite before 3.35 can't drop columns, so the table is rebuilt with only the first response
+CREATE TABLE autoresponses_new (
+    autoresponse_id INTEGER PRIMARY KEY ASC NOT NULL,
+    pattern TEXT NOT NULL,
+    match_kind TEXT NOT NULL,
+    case_sensitive BOOLEAN NOT NULL DEFAULT 1,
+    response TEXT NOT NULL,
+    moderator_only BOOLEAN NOT NULL DEFAULT 0,
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    channel_cooldown_secs INTEGER NOT NULL DEFAULT 0,
+    user_cooldown_secs INTEGER NOT NULL DEFAULT 0,
+    chance_percent INTEGER NOT NULL DEFAULT 100
+);
+INSERT INTO autoresponses_new (autoresponse_id, pattern, match_kind, case_sensitive, response, moderator_only, enabled, channel_cooldown_secs, user_cooldown_secs, chance_percent)
+    SELECT
+        autoresponse_id,
+        pattern,
+        match_kind,
+        case_sensitive,
+        COALESCE((
+            SELECT REPLACE(r.response, '{mention:84774207140945920}', '<@84774207140945920>')
+            FROM autoresponse_responses r
+            WHERE r.autoresponse_id = autoresponses.autoresponse_id
+            ORDER BY r.response_id
+            LIMIT 1
+        ), ''),
+        moderator_only,
+        enabled,
+        channel_cooldown_secs,
+        user_cooldown_secs,
+        chance_percent
+    FROM autoresponses;
+
+DROP TABLE autoresponse_responses;
+DROP TABLE autoresponses;
+ALTER TABLE autoresponses_new RENAME TO autoresponses;
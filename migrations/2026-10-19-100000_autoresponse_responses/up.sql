@@ -0,0 +1,36 @@
+-- Your SQL goes here
+-- SQLite before 3.35 can't drop columns, so the table is rebuilt without `response`. The responses
+-- are set aside first, that way dropping the old table can't take them along.
+CREATE TABLE old_responses AS
+    SELECT autoresponse_id, response FROM autoresponses;
+
+CREATE TABLE autoresponses_new (
+    autoresponse_id INTEGER PRIMARY KEY ASC NOT NULL,
+    pattern TEXT NOT NULL,
+    match_kind TEXT NOT NULL,
+    case_sensitive BOOLEAN NOT NULL DEFAULT 1,
+    moderator_only BOOLEAN NOT NULL DEFAULT 0,
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    channel_cooldown_secs INTEGER NOT NULL DEFAULT 0,
+    user_cooldown_secs INTEGER NOT NULL DEFAULT 0,
+    chance_percent INTEGER NOT NULL DEFAULT 100,
+    trigger_count INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO autoresponses_new (autoresponse_id, pattern, match_kind, case_sensitive, moderator_only, enabled, channel_cooldown_secs, user_cooldown_secs, chance_percent)
+    SELECT autoresponse_id, pattern, match_kind, case_sensitive, moderator_only, enabled, channel_cooldown_secs, user_cooldown_secs, chance_percent FROM autoresponses;
+DROP TABLE autoresponses;
+ALTER TABLE autoresponses_new RENAME TO autoresponses;
+
+CREATE TABLE autoresponse_responses (
+    response_id INTEGER PRIMARY KEY ASC NOT NULL,
+    autoresponse_id INTEGER NOT NULL REFERENCES autoresponses (autoresponse_id) ON DELETE CASCADE,
+    response TEXT NOT NULL
+);
+
+INSERT INTO autoresponse_responses (autoresponse_id, response)
+    SELECT autoresponse_id, response FROM old_responses ORDER BY autoresponse_id;
+DROP TABLE old_responses;
+
+-- the radio lag responses used to mention Lumi by hand
+UPDATE autoresponse_responses
+    SET response = REPLACE(response, '<@84774207140945920>', '{mention:84774207140945920}');
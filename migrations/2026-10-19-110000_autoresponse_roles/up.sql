@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- comma separated role IDs, empty means everyone can trigger the autoresponse
+-- (`moderator_only` autoresponses are converted to the IDs of the "Moderator" roles once the bot's cache is ready)
+ALTER TABLE autoresponses ADD COLUMN allowed_role_ids TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+-- rules without an autoresponse apply to all autoresponses of the guild, `channel_id` can also be a category
+CREATE TABLE autoresponder_channel_rules (
+    rule_id INTEGER PRIMARY KEY ASC NOT NULL,
+    guild_id BIGINT NOT NULL,
+    autoresponse_id INTEGER REFERENCES autoresponses (autoresponse_id) ON DELETE CASCADE,
+    channel_id BIGINT NOT NULL,
+    allow BOOLEAN NOT NULL
+);
@@ -0,0 +1,20 @@
+-- This file should undo anything in `up.sql`
+-- SQLite before 3.35 can't drop columns, so the table is rebuilt without them
+-- (foreign keys are off while migrating, so the rows referencing it survive the drop)
+CREATE TABLE autoresponses_new (
+    autoresponse_id INTEGER PRIMARY KEY ASC NOT NULL,
+    pattern TEXT NOT NULL,
+    match_kind TEXT NOT NULL,
+    case_sensitive BOOLEAN NOT NULL DEFAULT 1,
+    moderator_only BOOLEAN NOT NULL DEFAULT 0,
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    channel_cooldown_secs INTEGER NOT NULL DEFAULT 0,
+    user_cooldown_secs INTEGER NOT NULL DEFAULT 0,
+    chance_percent INTEGER NOT NULL DEFAULT 100,
+    trigger_count INTEGER NOT NULL DEFAULT 0,
+    allowed_role_ids TEXT NOT NULL DEFAULT ''
+);
+INSERT INTO autoresponses_new (autoresponse_id, pattern, match_kind, case_sensitive, moderator_only, enabled, channel_cooldown_secs, user_cooldown_secs, chance_percent, trigger_count, allowed_role_ids)
+    SELECT autoresponse_id, pattern, match_kind, case_sensitive, moderator_only, enabled, channel_cooldown_secs, user_cooldown_secs, chance_percent, trigger_count, allowed_role_ids FROM autoresponses;
+DROP TABLE autoresponses;
+ALTER TABLE autoresponses_new RENAME TO autoresponses;
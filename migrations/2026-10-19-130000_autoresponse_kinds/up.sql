@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE autoresponses ADD COLUMN response_kind TEXT NOT NULL DEFAULT 'reply';
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE autoresponse_stats (
+    autoresponse_id INTEGER NOT NULL REFERENCES autoresponses (autoresponse_id) ON DELETE CASCADE,
+    guild_id BIGINT NOT NULL,
+    channel_id BIGINT NOT NULL,
+    day DATE NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (autoresponse_id, guild_id, channel_id, day)
+);
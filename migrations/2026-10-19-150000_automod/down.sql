@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE automod_exemptions;
+DROP TABLE guild_settings;
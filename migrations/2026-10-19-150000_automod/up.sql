@@ -0,0 +1,20 @@
+-- Your SQL goes here
+-- one row per guild, later features add their own columns
+CREATE TABLE guild_settings (
+    guild_id BIGINT PRIMARY KEY NOT NULL,
+    automod_enabled BOOLEAN NOT NULL DEFAULT 0,
+    automod_profanity TEXT NOT NULL DEFAULT 'off',
+    automod_offensive TEXT NOT NULL DEFAULT 'moderate',
+    automod_sexual TEXT NOT NULL DEFAULT 'off',
+    automod_mean TEXT NOT NULL DEFAULT 'off',
+    automod_action TEXT NOT NULL DEFAULT 'log',
+    automod_timeout_secs INTEGER NOT NULL DEFAULT 600
+);
+
+-- `target_id` is a role if `is_role` is set, otherwise a channel or category
+CREATE TABLE automod_exemptions (
+    exemption_id INTEGER PRIMARY KEY ASC NOT NULL,
+    guild_id BIGINT NOT NULL,
+    target_id BIGINT NOT NULL,
+    is_role BOOLEAN NOT NULL
+);
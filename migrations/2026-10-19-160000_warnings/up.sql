@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE warnings (
+    warning_id INTEGER PRIMARY KEY ASC NOT NULL,
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    moderator_id BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT 1
+);
+
+-- once a member reaches `warning_count` active warnings, `action` is applied to them
+CREATE TABLE warning_escalations (
+    escalation_id INTEGER PRIMARY KEY ASC NOT NULL,
+    guild_id BIGINT NOT NULL,
+    warning_count INTEGER NOT NULL,
+    action TEXT NOT NULL,
+    timeout_secs INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE mod_cases (
+    case_id INTEGER PRIMARY KEY ASC NOT NULL,
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    moderator_id BIGINT NOT NULL,
+    action TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    -- when a timeout or temporary ban ends
+    expires_at TIMESTAMP,
+    -- set once a temporary ban has been lifted or replaced by another ban
+    lifted BOOLEAN NOT NULL DEFAULT 0,
+    -- where the case was posted in the moderation log
+    log_channel_id BIGINT,
+    log_message_id BIGINT
+);
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TABLE guild_settings ADD COLUMN antispam_enabled BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE guild_settings ADD COLUMN antispam_window_secs INTEGER NOT NULL DEFAULT 10;
+ALTER TABLE guild_settings ADD COLUMN antispam_max_messages INTEGER NOT NULL DEFAULT 6;
+ALTER TABLE guild_settings ADD COLUMN antispam_max_duplicates INTEGER NOT NULL DEFAULT 3;
+ALTER TABLE guild_settings ADD COLUMN antispam_max_mentions INTEGER NOT NULL DEFAULT 8;
+ALTER TABLE guild_settings ADD COLUMN antispam_action TEXT NOT NULL DEFAULT 'delete';
+ALTER TABLE guild_settings ADD COLUMN antispam_timeout_secs INTEGER NOT NULL DEFAULT 300;
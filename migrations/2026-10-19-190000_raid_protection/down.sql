@@ -0,0 +1,23 @@
+-- This file should undo anything in `up.sql`
+-- SQLite before 3.35 can't drop columns, so the table is rebuilt without them
+CREATE TABLE guild_settings_new (
+    guild_id BIGINT PRIMARY KEY NOT NULL,
+    automod_enabled BOOLEAN NOT NULL DEFAULT 0,
+    automod_profanity TEXT NOT NULL DEFAULT 'off',
+    automod_offensive TEXT NOT NULL DEFAULT 'moderate',
+    automod_sexual TEXT NOT NULL DEFAULT 'off',
+    automod_mean TEXT NOT NULL DEFAULT 'off',
+    automod_action TEXT NOT NULL DEFAULT 'log',
+    automod_timeout_secs INTEGER NOT NULL DEFAULT 600,
+    antispam_enabled BOOLEAN NOT NULL DEFAULT 0,
+    antispam_window_secs INTEGER NOT NULL DEFAULT 10,
+    antispam_max_messages INTEGER NOT NULL DEFAULT 6,
+    antispam_max_duplicates INTEGER NOT NULL DEFAULT 3,
+    antispam_max_mentions INTEGER NOT NULL DEFAULT 8,
+    antispam_action TEXT NOT NULL DEFAULT 'delete',
+    antispam_timeout_secs INTEGER NOT NULL DEFAULT 300
+);
+INSERT INTO guild_settings_new (guild_id, automod_enabled, automod_profanity, automod_offensive, automod_sexual, automod_mean, automod_action, automod_timeout_secs, antispam_enabled, antispam_window_secs, antispam_max_messages, antispam_max_duplicates, antispam_max_mentions, antispam_action, antispam_timeout_secs)
+    SELECT guild_id, automod_enabled, automod_profanity, automod_offensive, automod_sexual, automod_mean, automod_action, automod_timeout_secs, antispam_enabled, antispam_window_secs, antispam_max_messages, antispam_max_duplicates, antispam_max_mentions, antispam_action, antispam_timeout_secs FROM guild_settings;
+DROP TABLE guild_settings;
+ALTER TABLE guild_settings_new RENAME TO guild_settings;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE guild_settings ADD COLUMN raid_enabled BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE guild_settings ADD COLUMN raid_window_secs INTEGER NOT NULL DEFAULT 60;
+ALTER TABLE guild_settings ADD COLUMN raid_max_joins INTEGER NOT NULL DEFAULT 10;
+ALTER TABLE guild_settings ADD COLUMN raid_max_suspicious INTEGER NOT NULL DEFAULT 4;
+ALTER TABLE guild_settings ADD COLUMN raid_new_account_secs INTEGER NOT NULL DEFAULT 604800;
+ALTER TABLE guild_settings ADD COLUMN raid_lockdown TEXT NOT NULL DEFAULT 'off';
+ALTER TABLE guild_settings ADD COLUMN lockdown_mode TEXT NULL;
+ALTER TABLE guild_settings ADD COLUMN lockdown_previous_verification INTEGER NULL;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE notes (
+    note_id INTEGER PRIMARY KEY ASC NOT NULL,
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    moderator_id BIGINT NOT NULL,
+    content TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
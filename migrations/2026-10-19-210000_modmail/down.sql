@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE modmail_messages;
+DROP TABLE modmail_tickets;
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+CREATE TABLE modmail_tickets (
+    ticket_id INTEGER PRIMARY KEY ASC NOT NULL,
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    thread_id BIGINT NOT NULL,
+    opened_at TIMESTAMP NOT NULL,
+    closed_at TIMESTAMP NULL,
+    closed_by BIGINT NULL
+);
+
+-- everything that was said in a ticket, for the transcript
+CREATE TABLE modmail_messages (
+    modmail_message_id INTEGER PRIMARY KEY ASC NOT NULL,
+    ticket_id INTEGER NOT NULL REFERENCES modmail_tickets (ticket_id) ON DELETE CASCADE,
+    author_id BIGINT NOT NULL,
+    author_tag TEXT NOT NULL,
+    from_staff BOOLEAN NOT NULL,
+    content TEXT NOT NULL,
+    attachments TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP NOT NULL
+);
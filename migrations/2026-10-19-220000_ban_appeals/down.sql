@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE appeal_votes;
+DROP TABLE ban_appeals;
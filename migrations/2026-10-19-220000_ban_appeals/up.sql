@@ -0,0 +1,22 @@
+-- Your SQL goes here
+CREATE TABLE ban_appeals (
+    appeal_id INTEGER PRIMARY KEY ASC NOT NULL,
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    case_id INTEGER NOT NULL REFERENCES mod_cases (case_id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    -- pending, accepted or denied
+    status TEXT NOT NULL DEFAULT 'pending',
+    decided_by BIGINT NULL,
+    decided_at TIMESTAMP NULL,
+    staff_channel_id BIGINT NULL,
+    staff_message_id BIGINT NULL
+);
+
+CREATE TABLE appeal_votes (
+    appeal_id INTEGER NOT NULL REFERENCES ban_appeals (appeal_id) ON DELETE CASCADE,
+    voter_id BIGINT NOT NULL,
+    approve BOOLEAN NOT NULL,
+    PRIMARY KEY (appeal_id, voter_id)
+);
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+ALTER TABLE guild_settings ADD COLUMN nickname_filter_enabled BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE guild_settings ADD COLUMN nickname_filter_severity TEXT NOT NULL DEFAULT 'moderate';
+ALTER TABLE guild_settings ADD COLUMN nickname_filter_action TEXT NOT NULL DEFAULT 'replace';
+ALTER TABLE guild_settings ADD COLUMN nickname_replacement TEXT NOT NULL DEFAULT 'Moderated Nickname';
+
+-- case insensitive regexes, names matching any of them are filtered too
+CREATE TABLE nickname_patterns (
+    pattern_id INTEGER PRIMARY KEY ASC NOT NULL,
+    guild_id BIGINT NOT NULL,
+    pattern TEXT NOT NULL
+);
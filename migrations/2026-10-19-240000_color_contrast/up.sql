@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE guild_settings ADD COLUMN color_contrast_mode TEXT NOT NULL DEFAULT 'warn';
@@ -0,0 +1,15 @@
+-- This file should undo anything in `up.sql`
+-- SQLite before 3.35 can't drop columns, so the table is rebuilt without them
+CREATE TABLE warnings_new (
+    warning_id INTEGER PRIMARY KEY ASC NOT NULL,
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    moderator_id BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT 1
+);
+INSERT INTO warnings_new (warning_id, guild_id, user_id, moderator_id, reason, created_at, active)
+    SELECT warning_id, guild_id, user_id, moderator_id, reason, created_at, active FROM warnings;
+DROP TABLE warnings;
+ALTER TABLE warnings_new RENAME TO warnings;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- the `warning_count` of the escalation a warning triggered, so it isn't applied twice
+ALTER TABLE warnings ADD COLUMN escalation_count INTEGER NULL;
@@ -0,0 +1,475 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use poise::{command, serenity_prelude::{self as serenity, Mentionable}};
+
+use crate::bot_modules::autoresponder::author_roles;
+use crate::bot_modules::{moderation, modlog};
+use crate::guild_settings::{ensure_guild_settings, get_all_guild_settings, get_guild_settings};
+use crate::models::{AntispamSettingsChanges, GuildSettings};
+use crate::{Context, Data, Error};
+
+const ANTISPAM_COLOUR: u32 = 0xe67e22;
+/// The longest window that can be configured, anything older than this is never needed.
+const MAX_WINDOW_SECS: i32 = 60;
+/// Once this many members are tracked, the ones that have been quiet for a while are forgotten.
+const TRACKED_MEMBERS_PRUNE_AT: usize = 1000;
+
+/// What anti-spam does when a member spams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Action {
+    // Delete the spam.
+    #[name = "delete"]
+    Delete,
+    // Delete the spam and time its author out.
+    #[name = "timeout"]
+    Timeout,
+    // Leave the spam alone, only alert the moderators in the moderation log.
+    #[name = "alert"]
+    Alert,
+}
+
+impl Action {
+    /// The name under which this action is stored in the database.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Action::Delete => "delete",
+            Action::Timeout => "timeout",
+            Action::Alert => "alert",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "delete" => Some(Action::Delete),
+            "timeout" => Some(Action::Timeout),
+            "alert" => Some(Action::Alert),
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Action::Delete => "deleted",
+            Action::Timeout => "deleted and its author timed out",
+            Action::Alert => "reported to the moderators",
+        }
+    }
+}
+
+/// Which limit a member went over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Violation {
+    Rate(usize),
+    Duplicates(usize),
+    Mentions(usize),
+}
+
+impl Violation {
+    fn describe(&self, window_secs: u64) -> String {
+        match self {
+            Violation::Rate(count) => format!("sent {} messages within {} seconds", count, window_secs),
+            Violation::Duplicates(count) => format!("sent the same message {} times within {} seconds", count, window_secs),
+            Violation::Mentions(count) => format!("mentioned {} users or roles within {} seconds", count, window_secs),
+        }
+    }
+}
+
+/// The anti-spam settings of a guild.
+#[derive(Debug, Clone)]
+pub struct AntispamConfig {
+    pub window: Duration,
+    pub max_messages: usize,
+    pub max_duplicates: usize,
+    pub max_mentions: usize,
+    pub action: Action,
+    pub timeout_secs: i32,
+}
+
+impl AntispamConfig {
+    fn new(settings: &GuildSettings) -> Self {
+        AntispamConfig {
+            window: Duration::from_secs(settings.antispam_window_secs.clamp(1, MAX_WINDOW_SECS) as u64),
+            max_messages: settings.antispam_max_messages.max(1) as usize,
+            max_duplicates: settings.antispam_max_duplicates.max(1) as usize,
+            max_mentions: settings.antispam_max_mentions.max(1) as usize,
+            action: Action::from_name(&settings.antispam_action).unwrap_or(Action::Delete),
+            timeout_secs: settings.antispam_timeout_secs,
+        }
+    }
+}
+
+/// What's remembered of a recent message to tell whether its author is spamming.
+struct RecentMessage {
+    at: Instant,
+    channel_id: serenity::ChannelId,
+    message_id: serenity::MessageId,
+    content: String,
+    mentions: usize,
+}
+
+impl RecentMessage {
+    fn from_message(message: &serenity::Message, at: Instant) -> Self {
+        RecentMessage {
+            at,
+            channel_id: message.channel_id,
+            message_id: message.id,
+            // so spam doesn't get past the duplicate check by changing case or adding spaces
+            content: message.content.trim().to_lowercase(),
+            mentions: message.mentions.len() + message.mention_roles.len() + message.mention_everyone as usize,
+        }
+    }
+}
+
+/// The recent messages of every member, per guild.
+#[derive(Default)]
+pub struct SpamTracker {
+    recent: HashMap<(serenity::GuildId, serenity::UserId), VecDeque<RecentMessage>>,
+}
+
+impl SpamTracker {
+    /// Remember a message and check the messages its author sent within the window.
+    ///
+    /// If they went over a limit, the messages within the window are returned and forgotten, so
+    /// the same messages aren't acted on twice.
+    fn record(
+        &mut self,
+        guild_id: serenity::GuildId,
+        user_id: serenity::UserId,
+        message: RecentMessage,
+        config: &AntispamConfig,
+    ) -> Option<(Violation, Vec<RecentMessage>)> {
+        let now = message.at;
+        if self.recent.len() >= TRACKED_MEMBERS_PRUNE_AT {
+            let max_window = Duration::from_secs(MAX_WINDOW_SECS as u64);
+            self.recent.retain(|_, messages| messages.back().map_or(false, |m| now.duration_since(m.at) < max_window));
+        }
+
+        let messages = self.recent.entry((guild_id, user_id)).or_default();
+        while messages.front().map_or(false, |m| now.duration_since(m.at) >= config.window) {
+            messages.pop_front();
+        }
+
+        let duplicates = if message.content.is_empty() {
+            0
+        } else {
+            messages.iter().filter(|m| m.content == message.content).count() + 1
+        };
+        messages.push_back(message);
+        let mentions: usize = messages.iter().map(|m| m.mentions).sum();
+
+        let violation = if mentions > config.max_mentions {
+            Violation::Mentions(mentions)
+        } else if duplicates > config.max_duplicates {
+            Violation::Duplicates(duplicates)
+        } else if messages.len() > config.max_messages {
+            Violation::Rate(messages.len())
+        } else {
+            return None;
+        };
+
+        let spam = self.recent.remove(&(guild_id, user_id)).unwrap_or_default();
+        Some((violation, spam.into()))
+    }
+}
+
+fn update_antispam_settings(conn: &SqliteConnection, of_guild: i64, changes: &AntispamSettingsChanges) {
+    use crate::schema::guild_settings::dsl::*;
+
+    ensure_guild_settings(conn, of_guild);
+    diesel::update(guild_settings.filter(guild_id.eq(of_guild)))
+        .set(changes)
+        .execute(conn)
+        .expect("Error updating guild settings");
+}
+
+/// Load the anti-spam settings of all guilds that have it enabled.
+pub fn load_antispam_configs(conn: &SqliteConnection) -> HashMap<serenity::GuildId, AntispamConfig> {
+    get_all_guild_settings(conn)
+        .into_iter()
+        .filter(|settings| settings.antispam_enabled)
+        .map(|settings| (serenity::GuildId(settings.guild_id as u64), AntispamConfig::new(&settings)))
+        .collect()
+}
+
+/// Reload the anti-spam cache, so changes to the settings take effect immediately.
+pub async fn reload_antispam_configs(data: &Data) {
+    let configs = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        load_antispam_configs(&conn)
+    };
+    *data.antispam.write().await = configs;
+}
+
+/// Delete spam, in bulk where possible.
+async fn delete_spam(ctx: &serenity::Context, data: &Data, spam: &[RecentMessage]) -> serenity::Result<()> {
+    let mut by_channel: HashMap<serenity::ChannelId, Vec<serenity::MessageId>> = HashMap::new();
+    {
+        // the anti-spam log entry replaces the usual deletion log
+        let mut cache = data.message_cache.lock().await;
+        for message in spam {
+            cache.skip_deletion_log(message.message_id);
+            by_channel.entry(message.channel_id).or_default().push(message.message_id);
+        }
+    }
+
+    for (channel_id, ids) in by_channel {
+        // bulk deletion needs at least two messages
+        if let [id] = ids.as_slice() {
+            channel_id.delete_message(ctx, *id).await?;
+        } else {
+            channel_id.delete_messages(ctx, &ids).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Track a message and act on its author if they're spamming.
+///
+/// Returns whether the spam was removed, in which case nothing else should respond to it.
+pub async fn handle_message(ctx: &serenity::Context, data: &Data, message: &serenity::Message) -> bool {
+    if message.author.bot {
+        return false;
+    }
+    let Some(guild_id) = message.guild_id else {
+        return false;
+    };
+    let Some(config) = data.antispam.read().await.get(&guild_id).cloned() else {
+        return false;
+    };
+    if moderation::is_moderator(ctx, guild_id, message.author.id, &author_roles(ctx, message)) {
+        return false;
+    }
+
+    let recent = RecentMessage::from_message(message, Instant::now());
+    let Some((violation, spam)) = data.spam_tracker.lock().await.record(guild_id, message.author.id, recent, &config) else {
+        return false;
+    };
+
+    let mut outcome = config.action.describe().to_string();
+    if config.action != Action::Alert {
+        if let Err(e) = delete_spam(ctx, data, &spam).await {
+            warn!("Couldn't delete spam: {}", e);
+            outcome = format!("not deleted ({})", e);
+        }
+    }
+    if config.action == Action::Timeout {
+        let reason = format!("Anti-spam: {}", violation.describe(config.window.as_secs()));
+        let result = moderation::apply_timeout(
+            ctx,
+            data,
+            guild_id,
+            &message.author,
+            ctx.cache.current_user_id(),
+            config.timeout_secs as i64,
+            &reason
+        ).await;
+        match result {
+            Ok(case) => outcome = format!("{} (case `#{}`)", outcome, case.case_id),
+            Err(e) => {
+                warn!("Couldn't time out spammer: {}", e);
+                outcome = format!("{}, but the timeout failed ({})", outcome, e);
+            },
+        }
+    }
+
+    let channels = {
+        let mut channels: Vec<serenity::ChannelId> = spam.iter().map(|m| m.channel_id).collect();
+        channels.sort();
+        channels.dedup();
+        channels.iter().map(|c| c.mention().to_string()).collect::<Vec<_>>().join(", ")
+    };
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title("Spam detected")
+        .colour(ANTISPAM_COLOUR)
+        .description(format!(
+            "{} ({}) {} in {}, the {} messages were {}",
+            message.author.mention(),
+            message.author.tag(),
+            violation.describe(config.window.as_secs()),
+            channels,
+            spam.len(),
+            outcome
+        ))
+        .field("Latest message", modlog::truncate(&message.content, 1024), false)
+        .footer(|f| f.text(format!("User ID: {}", message.author.id)))
+        .timestamp(chrono::Utc::now());
+    modlog::post(ctx, data, guild_id, embed, None).await;
+
+    config.action != Action::Alert
+}
+
+/// Configure the detection of spam
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("antispam_settings", "antispam_toggle", "antispam_limits", "antispam_action")
+)]
+pub async fn antispam(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show the anti-spam settings of this server
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "settings")]
+pub async fn antispam_settings(ctx: Context<'_>) -> Result<(), Error> {
+    let settings = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        get_guild_settings(&conn, ctx.guild_id().unwrap().0 as i64)
+    };
+
+    let mut action = settings.antispam_action.clone();
+    if Action::from_name(&action) == Some(Action::Timeout) {
+        action.push_str(&format!(" for {} minutes", settings.antispam_timeout_secs / 60));
+    }
+
+    ctx.say(format!(
+        "Anti-spam is **{}**.\nWithin {} seconds, members may send up to {} messages, {} identical messages and {} mentions.\nAction: {}\nModerators are always exempt.",
+        if settings.antispam_enabled { "enabled" } else { "disabled" },
+        settings.antispam_window_secs,
+        settings.antispam_max_messages,
+        settings.antispam_max_duplicates,
+        settings.antispam_max_mentions,
+        action
+    )).await?;
+
+    Ok(())
+}
+
+/// Turn anti-spam on or off
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "toggle")]
+pub async fn antispam_toggle(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let enabled = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        let enabled = !get_guild_settings(&conn, guild_id).antispam_enabled;
+        update_antispam_settings(&conn, guild_id, &AntispamSettingsChanges {
+            antispam_enabled: Some(enabled),
+            ..Default::default()
+        });
+        enabled
+    };
+    reload_antispam_configs(ctx.data()).await;
+
+    ctx.say(format!("Anti-spam is now {}.", if enabled { "enabled" } else { "disabled" })).await?;
+
+    Ok(())
+}
+
+/// Set how much members may send before it counts as spam
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "limits")]
+pub async fn antispam_limits(
+    ctx: Context<'_>,
+    #[description = "How many seconds back messages are counted"] #[min = 1] #[max = 60] window_seconds: Option<i32>,
+    #[description = "How many messages may be sent within the window"] #[min = 1] #[max = 50] messages: Option<i32>,
+    #[description = "How many identical messages may be sent within the window"] #[min = 1] #[max = 50] duplicates: Option<i32>,
+    #[description = "How many users and roles may be mentioned within the window"] #[min = 1] #[max = 100] mentions: Option<i32>,
+) -> Result<(), Error> {
+    if window_seconds.is_none() && messages.is_none() && duplicates.is_none() && mentions.is_none() {
+        ctx.say("Give at least one limit to change.").await?;
+        return Ok(());
+    }
+
+    {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        update_antispam_settings(&conn, ctx.guild_id().unwrap().0 as i64, &AntispamSettingsChanges {
+            antispam_window_secs: window_seconds,
+            antispam_max_messages: messages,
+            antispam_max_duplicates: duplicates,
+            antispam_max_mentions: mentions,
+            ..Default::default()
+        });
+    }
+    reload_antispam_configs(ctx.data()).await;
+
+    ctx.say("The anti-spam limits were updated.").await?;
+
+    Ok(())
+}
+
+/// Set what anti-spam does when a member spams
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "action")]
+pub async fn antispam_action(
+    ctx: Context<'_>,
+    #[description = "What to do with spam"] action: Action,
+    #[description = "How long timeouts last in minutes (default: 5)"] #[min = 1] #[max = 40320] timeout_minutes: Option<i32>,
+) -> Result<(), Error> {
+    {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        update_antispam_settings(&conn, ctx.guild_id().unwrap().0 as i64, &AntispamSettingsChanges {
+            antispam_action: Some(action.as_str()),
+            antispam_timeout_secs: timeout_minutes.map(|m| m * 60),
+            ..Default::default()
+        });
+    }
+    reload_antispam_configs(ctx.data()).await;
+
+    ctx.say(format!("Spam is now {}.", action.describe())).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AntispamConfig {
+        AntispamConfig {
+            window: Duration::from_secs(10),
+            max_messages: 4,
+            max_duplicates: 2,
+            max_mentions: 5,
+            action: Action::Delete,
+            timeout_secs: 300,
+        }
+    }
+
+    fn recent(id: u64, at: Instant, content: &str, mentions: usize) -> RecentMessage {
+        RecentMessage {
+            at,
+            channel_id: serenity::ChannelId(1),
+            message_id: serenity::MessageId(id),
+            content: content.to_string(),
+            mentions,
+        }
+    }
+
+    #[test]
+    fn test_spam_tracker() {
+        let config = config();
+        let guild = serenity::GuildId(1);
+        let user = serenity::UserId(2);
+        let start = Instant::now();
+        let mut tracker = SpamTracker::default();
+
+        // messages that fall out of the window don't count
+        for i in 0..4 {
+            assert!(tracker.record(guild, user, recent(i, start + Duration::from_secs(i * 5), &i.to_string(), 0), &config).is_none());
+        }
+        let (violation, spam) = (4..8)
+            .find_map(|i| tracker.record(guild, user, recent(i, start + Duration::from_secs(20), &i.to_string(), 0), &config))
+            .unwrap();
+        assert_eq!(violation, Violation::Rate(5));
+        assert_eq!(spam.len(), 5);
+
+        // the spam is forgotten once it was acted on
+        let later = start + Duration::from_secs(21);
+        assert!(tracker.record(guild, user, recent(7, later, "hi", 0), &config).is_none());
+        assert!(tracker.record(guild, user, recent(8, later, "hi", 0), &config).is_none());
+        let (violation, _) = tracker.record(guild, user, recent(9, later, "hi", 0), &config).unwrap();
+        assert_eq!(violation, Violation::Duplicates(3));
+
+        let (violation, spam) = tracker.record(guild, user, recent(10, later, "@everyone", 6), &config).unwrap();
+        assert_eq!(violation, Violation::Mentions(6));
+        assert_eq!(spam.len(), 1);
+
+        // other members are tracked separately
+        let other = serenity::UserId(3);
+        assert!(tracker.record(guild, other, recent(11, later, "hi", 0), &config).is_none());
+    }
+}
@@ -0,0 +1,503 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use poise::{command, serenity_prelude::{self as serenity, Mentionable}};
+
+use crate::bot_modules::{moderation, modlog};
+use crate::models::{AppealVote, BanAppeal, ModCase, NewBanAppeal};
+use crate::{Context, Data, Error};
+
+const PENDING_COLOUR: u32 = 0xfee75c;
+const ACCEPTED_COLOUR: u32 = 0x57f287;
+const DENIED_COLOUR: u32 = 0xed4245;
+/// Custom IDs of the appeal buttons start with this, followed by the appeal ID and the choice.
+const BUTTON_PREFIX: &str = "appeal:";
+
+/// Where an appeal stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AppealStatus {
+    Pending,
+    Accepted,
+    Denied,
+}
+
+impl AppealStatus {
+    /// The name under which this status is stored in the database.
+    fn as_str(&self) -> &'static str {
+        match self {
+            AppealStatus::Pending => "pending",
+            AppealStatus::Accepted => "accepted",
+            AppealStatus::Denied => "denied",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pending" => Some(AppealStatus::Pending),
+            "accepted" => Some(AppealStatus::Accepted),
+            "denied" => Some(AppealStatus::Denied),
+            _ => None,
+        }
+    }
+}
+
+/// A button on an appeal in the staff channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Choice {
+    Vote(bool),
+    Accept,
+    Deny,
+}
+
+impl Choice {
+    fn custom_id(&self, appeal_id: i32) -> String {
+        let choice = match self {
+            Choice::Vote(true) => "up",
+            Choice::Vote(false) => "down",
+            Choice::Accept => "accept",
+            Choice::Deny => "deny",
+        };
+        format!("{}{}:{}", BUTTON_PREFIX, appeal_id, choice)
+    }
+
+    fn parse(custom_id: &str) -> Option<(i32, Self)> {
+        let (appeal_id, choice) = custom_id.strip_prefix(BUTTON_PREFIX)?.split_once(':')?;
+        let choice = match choice {
+            "up" => Choice::Vote(true),
+            "down" => Choice::Vote(false),
+            "accept" => Choice::Accept,
+            "deny" => Choice::Deny,
+            _ => return None,
+        };
+
+        Some((appeal_id.parse().ok()?, choice))
+    }
+}
+
+fn create_appeal(conn: &SqliteConnection, new_appeal: &NewBanAppeal) -> BanAppeal {
+    use crate::schema::ban_appeals::dsl::*;
+
+    diesel::insert_into(ban_appeals)
+        .values(new_appeal)
+        .execute(conn)
+        .expect("Error creating ban appeal");
+
+    ban_appeals.order(appeal_id.desc()).first(conn).unwrap()
+}
+
+fn get_appeal(conn: &SqliteConnection, to_get: i32) -> Option<BanAppeal> {
+    use crate::schema::ban_appeals::dsl::*;
+
+    ban_appeals
+        .filter(appeal_id.eq(to_get))
+        .first(conn)
+        .ok()
+}
+
+fn get_appeal_of_case(conn: &SqliteConnection, of_case: i32) -> Option<BanAppeal> {
+    use crate::schema::ban_appeals::dsl::*;
+
+    ban_appeals
+        .filter(case_id.eq(of_case))
+        .first(conn)
+        .ok()
+}
+
+fn set_staff_message(conn: &SqliteConnection, to_update: i32, channel: serenity::ChannelId, message: serenity::MessageId) {
+    use crate::schema::ban_appeals::dsl::*;
+
+    diesel::update(ban_appeals.filter(appeal_id.eq(to_update)))
+        .set((staff_channel_id.eq(channel.0 as i64), staff_message_id.eq(message.0 as i64)))
+        .execute(conn)
+        .expect("Error updating ban appeal");
+}
+
+/// Mark a pending appeal as decided, in one statement so only one moderator gets to decide it.
+///
+/// Returns whether the appeal was still pending.
+fn claim_decision(conn: &SqliteConnection, to_update: i32, new_status: AppealStatus, by: i64) -> QueryResult<bool> {
+    use crate::schema::ban_appeals::dsl::*;
+
+    let claimed = diesel::update(ban_appeals.filter(appeal_id.eq(to_update)).filter(status.eq(AppealStatus::Pending.as_str())))
+        .set((status.eq(new_status.as_str()), decided_by.eq(by), decided_at.eq(Utc::now().naive_utc())))
+        .execute(conn)?;
+
+    Ok(claimed == 1)
+}
+
+/// Put a claimed appeal back to pending, when its decision couldn't be carried out.
+fn release_decision(conn: &SqliteConnection, to_update: i32) {
+    use crate::schema::ban_appeals::dsl::*;
+
+    diesel::update(ban_appeals.filter(appeal_id.eq(to_update)))
+        .set((status.eq(AppealStatus::Pending.as_str()), decided_by.eq(None::<i64>), decided_at.eq(None::<NaiveDateTime>)))
+        .execute(conn)
+        .expect("Error updating ban appeal");
+}
+
+/// Remove an appeal that never made it to the staff channel, so the user can appeal again.
+fn withdraw_appeal(conn: &SqliteConnection, to_delete: i32) {
+    use crate::schema::ban_appeals::dsl::*;
+
+    diesel::delete(ban_appeals.filter(appeal_id.eq(to_delete)))
+        .execute(conn)
+        .expect("Error deleting ban appeal");
+}
+
+fn set_vote(conn: &SqliteConnection, vote: &AppealVote) {
+    use crate::schema::appeal_votes;
+
+    diesel::replace_into(appeal_votes::table)
+        .values(vote)
+        .execute(conn)
+        .expect("Error storing appeal vote");
+}
+
+fn get_votes(conn: &SqliteConnection, of_appeal: i32) -> Vec<AppealVote> {
+    use crate::schema::appeal_votes::dsl::*;
+
+    appeal_votes
+        .filter(appeal_id.eq(of_appeal))
+        .load(conn)
+        .expect("Error loading appeal votes")
+}
+
+fn get_case(conn: &SqliteConnection, of_guild: i64, of_user: i64, to_get: i32) -> Option<ModCase> {
+    moderation::get_cases(conn, of_guild, of_user).into_iter().find(|case| case.case_id == to_get)
+}
+
+/// The channel appeals are posted to, if there is one and it belongs to the given guild.
+pub fn appeal_channel(ctx: &serenity::Context, data: &Data, guild_id: serenity::GuildId) -> Option<serenity::ChannelId> {
+    let appeal_channel_id = data.appeal_channel_id?;
+    let appeal_channel = ctx.cache.guild_channel(appeal_channel_id)?;
+
+    (appeal_channel.guild_id == guild_id).then_some(appeal_channel_id)
+}
+
+fn describe_votes(votes: &[AppealVote], approve: bool) -> String {
+    let voters = votes
+        .iter()
+        .filter(|v| v.approve == approve)
+        .map(|v| serenity::UserId(v.voter_id as u64).mention().to_string())
+        .collect::<Vec<_>>();
+
+    if voters.is_empty() {
+        "nobody".to_string()
+    } else {
+        format!("{}: {}", voters.len(), voters.join(", "))
+    }
+}
+
+/// The embed an appeal is shown with in the staff channel.
+fn appeal_embed(appeal: &BanAppeal, case: Option<&ModCase>, user_tag: &str, votes: &[AppealVote]) -> serenity::CreateEmbed {
+    let status = AppealStatus::from_name(&appeal.status).unwrap_or(AppealStatus::Pending);
+    let decider = appeal.decided_by.map(|id| serenity::UserId(id as u64).mention().to_string()).unwrap_or_default();
+    let (colour, status) = match status {
+        AppealStatus::Pending => (PENDING_COLOUR, "Pending".to_string()),
+        AppealStatus::Accepted => (ACCEPTED_COLOUR, format!("Accepted by {}", decider)),
+        AppealStatus::Denied => (DENIED_COLOUR, format!("Denied by {}", decider)),
+    };
+
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title(format!("Ban appeal #{}", appeal.appeal_id))
+        .colour(colour)
+        .description(format!("{} ({}) appeals their ban", serenity::UserId(appeal.user_id as u64).mention(), user_tag))
+        .field("Ban", case.map_or(format!("case `#{}`", appeal.case_id), moderation::describe_case), false)
+        .field("Appeal", modlog::truncate(&appeal.content, 1024), false)
+        .field("For unbanning", describe_votes(votes, true), true)
+        .field("Against unbanning", describe_votes(votes, false), true)
+        .field("Status", status, false)
+        .footer(|f| f.text(format!("User ID: {}", appeal.user_id)))
+        .timestamp(chrono::DateTime::<Utc>::from_utc(appeal.created_at, Utc));
+
+    embed
+}
+
+fn appeal_buttons(components: &mut serenity::CreateComponents, appeal_id: i32) -> &mut serenity::CreateComponents {
+    components.create_action_row(|r| r
+        .create_button(|b| b
+            .custom_id(Choice::Vote(true).custom_id(appeal_id))
+            .label("Vote to unban")
+            .style(serenity::ButtonStyle::Secondary)
+        )
+        .create_button(|b| b
+            .custom_id(Choice::Vote(false).custom_id(appeal_id))
+            .label("Vote to keep banned")
+            .style(serenity::ButtonStyle::Secondary)
+        )
+        .create_button(|b| b
+            .custom_id(Choice::Accept.custom_id(appeal_id))
+            .label("Accept")
+            .style(serenity::ButtonStyle::Success)
+        )
+        .create_button(|b| b
+            .custom_id(Choice::Deny.custom_id(appeal_id))
+            .label("Deny")
+            .style(serenity::ButtonStyle::Danger)
+        )
+    )
+}
+
+/// File an appeal from a direct message and post it to the staff channel.
+async fn submit_appeal(
+    ctx: &serenity::Context,
+    data: &Data,
+    appeal_channel_id: serenity::ChannelId,
+    case: &ModCase,
+    message: &serenity::Message,
+) -> Result<(), Error> {
+    let appeal = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        create_appeal(&conn, &NewBanAppeal {
+            guild_id: case.guild_id,
+            user_id: case.user_id,
+            case_id: case.case_id,
+            content: &message.content,
+            created_at: Utc::now().naive_utc(),
+        })
+    };
+
+    let embed = appeal_embed(&appeal, Some(case), &message.author.tag(), &[]);
+    let posted = appeal_channel_id.send_message(ctx, |m| m
+        .set_embed(embed)
+        .components(|c| appeal_buttons(c, appeal.appeal_id))
+    ).await;
+
+    let conn = data.pool.get().expect("Couldn't get connection from pool");
+    match posted {
+        Ok(staff_message) => {
+            set_staff_message(&conn, appeal.appeal_id, appeal_channel_id, staff_message.id);
+            Ok(())
+        },
+        Err(e) => {
+            // nobody can see the appeal, so it mustn't keep the user from appealing again
+            withdraw_appeal(&conn, appeal.appeal_id);
+            Err(e.into())
+        },
+    }
+}
+
+/// Take ban appeals from banned users in direct messages.
+///
+/// Returns whether the message was about an appeal, in which case nothing else should respond to it.
+pub async fn handle_message(ctx: &serenity::Context, data: &Data, message: &serenity::Message) -> bool {
+    if message.author.bot || message.guild_id.is_some() {
+        return false;
+    }
+    let Some(appeal_channel_id) = data.appeal_channel_id else {
+        return false;
+    };
+    let Some(guild_id) = ctx.cache.guild_channel(appeal_channel_id).map(|c| c.guild_id) else {
+        return false;
+    };
+
+    let (case, appeal) = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        let Some(case) = moderation::get_active_ban(&conn, guild_id.0 as i64, message.author.id.0 as i64) else {
+            return false;
+        };
+        let appeal = get_appeal_of_case(&conn, case.case_id);
+        (case, appeal)
+    };
+
+    let reply = match appeal.as_ref().and_then(|a| AppealStatus::from_name(&a.status)) {
+        Some(AppealStatus::Denied) => "Your appeal against this ban was denied, so it can't be appealed again.".to_string(),
+        Some(_) => "Your appeal is being reviewed by the moderators, you'll hear back here once they've decided.".to_string(),
+        None if message.content.trim().is_empty() => "To appeal your ban, please explain in a message why you should be unbanned.".to_string(),
+        None => match submit_appeal(ctx, data, appeal_channel_id, &case, message).await {
+            Ok(()) => "Your appeal was sent to the moderators, you'll hear back here once they've decided.".to_string(),
+            Err(e) => {
+                error!("Couldn't submit a ban appeal: {}", e);
+                "Sorry, your appeal couldn't be sent to the moderators. Please try again later.".to_string()
+            },
+        },
+    };
+    let _ = message.channel_id.say(ctx, reply).await;
+
+    true
+}
+
+/// Decide an appeal, carry out the decision and tell the user.
+async fn decide(ctx: &serenity::Context, data: &Data, appeal: &BanAppeal, user: &serenity::User, moderator_id: serenity::UserId, accept: bool) -> Result<(), Error> {
+    let claimed = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        let status = if accept { AppealStatus::Accepted } else { AppealStatus::Denied };
+        claim_decision(&conn, appeal.appeal_id, status, moderator_id.0 as i64)?
+    };
+    if !claimed {
+        return Err("That appeal was already decided.".into());
+    }
+
+    let guild_id = serenity::GuildId(appeal.guild_id as u64);
+    let guild_name = guild_id.name(ctx).unwrap_or_else(|| "the server".to_string());
+    let notice = if accept {
+        let reason = format!("Ban appeal #{} accepted", appeal.appeal_id);
+        if let Err(e) = moderation::apply_unban(ctx, data, guild_id, user, moderator_id, &reason).await {
+            let conn = data.pool.get().expect("Couldn't get connection from pool");
+            release_decision(&conn, appeal.appeal_id);
+            return Err(e);
+        }
+        format!("Your ban appeal for **{}** was accepted, you have been unbanned.", guild_name)
+    } else {
+        format!("Your ban appeal for **{}** was denied.", guild_name)
+    };
+    let _ = user.direct_message(ctx, |m| m.content(notice)).await;
+
+    Ok(())
+}
+
+/// Handle the buttons on appeals in the staff channel. They keep working after a restart, so
+/// they're handled here instead of with a collector.
+pub async fn handle_interaction(ctx: &serenity::Context, data: &Data, interaction: &serenity::Interaction) -> Result<(), Error> {
+    let serenity::Interaction::MessageComponent(press) = interaction else {
+        return Ok(());
+    };
+    let Some((appeal_id, choice)) = Choice::parse(&press.data.custom_id) else {
+        return Ok(());
+    };
+
+    let may_decide = press.member.as_ref().and_then(|m| m.permissions).map_or(false, |p| p.ban_members());
+    let appeal = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        get_appeal(&conn, appeal_id)
+    };
+    let problem = match &appeal {
+        _ if !may_decide => Some("Only moderators who can ban members can do that."),
+        None => Some("That appeal doesn't exist anymore."),
+        Some(appeal) if appeal.status != AppealStatus::Pending.as_str() => Some("That appeal was already decided."),
+        _ => None,
+    };
+    if let Some(problem) = problem {
+        press.create_interaction_response(ctx, |ir| ir
+            .kind(serenity::InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|ird| ird.content(problem).ephemeral(true))
+        ).await?;
+        return Ok(());
+    }
+    let appeal = appeal.unwrap();
+
+    // unbanning and sending DMs can take longer than an interaction may go unanswered
+    press.defer(ctx).await?;
+
+    let user = serenity::UserId(appeal.user_id as u64).to_user(ctx).await?;
+    let result = match choice {
+        Choice::Vote(approve) => {
+            let conn = data.pool.get().expect("Couldn't get connection from pool");
+            set_vote(&conn, &AppealVote { appeal_id, voter_id: press.user.id.0 as i64, approve });
+            Ok(())
+        },
+        Choice::Accept => decide(ctx, data, &appeal, &user, press.user.id, true).await,
+        Choice::Deny => decide(ctx, data, &appeal, &user, press.user.id, false).await,
+    };
+    // only the moderator whose decision went through logs it
+    let decided = matches!(choice, Choice::Accept | Choice::Deny) && result.is_ok();
+    if let Err(e) = &result {
+        press.create_followup_message(ctx, |f| f
+            .content(format!("That didn't work: {}", e))
+            .ephemeral(true)
+        ).await?;
+    }
+
+    let (appeal, case, votes) = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        let appeal = get_appeal(&conn, appeal_id).unwrap();
+        let case = get_case(&conn, appeal.guild_id, appeal.user_id, appeal.case_id);
+        let votes = get_votes(&conn, appeal_id);
+        (appeal, case, votes)
+    };
+    let pending = appeal.status == AppealStatus::Pending.as_str();
+    let embed = appeal_embed(&appeal, case.as_ref(), &user.tag(), &votes);
+    press.edit_original_interaction_response(ctx, |r| r
+        .set_embed(embed)
+        .components(|c| if pending { appeal_buttons(c, appeal_id) } else { c })
+    ).await?;
+
+    if decided {
+        let mut embed = serenity::CreateEmbed::default();
+        embed
+            .title(format!("Ban appeal #{} {}", appeal.appeal_id, appeal.status))
+            .colour(if appeal.status == AppealStatus::Accepted.as_str() { ACCEPTED_COLOUR } else { DENIED_COLOUR })
+            .description(format!(
+                "{} {} the ban appeal of {} ({})",
+                press.user.mention(),
+                appeal.status,
+                user.mention(),
+                user.tag()
+            ))
+            .field("Appeal", modlog::truncate(&appeal.content, 1024), false)
+            .footer(|f| f.text(format!("User ID: {}", user.id)))
+            .timestamp(Utc::now());
+        modlog::post(ctx, data, serenity::GuildId(appeal.guild_id as u64), embed, None).await;
+    }
+
+    Ok(())
+}
+
+/// Explain how to appeal a ban
+#[command(slash_command, prefix_command, aliases("bananapeel"))]
+pub async fn banappeal(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("If you were banned, send me a direct message explaining why you should be unbanned. The moderators will look at your appeal and you'll hear back from me.").await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::connection::SimpleConnection;
+
+    use super::*;
+
+    fn appeals_db() -> SqliteConnection {
+        let conn = SqliteConnection::establish(":memory:").unwrap();
+        conn.batch_execute(include_str!("../../migrations/2026-10-19-220000_ban_appeals/up.sql")).unwrap();
+        conn
+    }
+
+    fn new_appeal(conn: &SqliteConnection) -> BanAppeal {
+        create_appeal(conn, &NewBanAppeal {
+            guild_id: 1,
+            user_id: 2,
+            case_id: 3,
+            content: "please",
+            created_at: Utc::now().naive_utc(),
+        })
+    }
+
+    #[test]
+    fn test_button_ids() {
+        for choice in [Choice::Vote(true), Choice::Vote(false), Choice::Accept, Choice::Deny] {
+            assert_eq!(Choice::parse(&choice.custom_id(42)), Some((42, choice)));
+        }
+        assert_eq!(Choice::parse("appeal:42:maybe"), None);
+        assert_eq!(Choice::parse("42prev"), None);
+    }
+
+    #[test]
+    fn test_withdrawn_appeal_can_be_filed_again() {
+        let conn = appeals_db();
+
+        // the staff message couldn't be posted
+        let appeal = new_appeal(&conn);
+        withdraw_appeal(&conn, appeal.appeal_id);
+        assert!(get_appeal_of_case(&conn, 3).is_none());
+
+        let appeal = new_appeal(&conn);
+        assert_eq!(get_appeal_of_case(&conn, 3).map(|a| a.appeal_id), Some(appeal.appeal_id));
+    }
+
+    #[test]
+    fn test_decision_is_claimed_once() {
+        let conn = appeals_db();
+        let appeal = new_appeal(&conn);
+
+        assert!(claim_decision(&conn, appeal.appeal_id, AppealStatus::Accepted, 4).unwrap());
+        assert!(!claim_decision(&conn, appeal.appeal_id, AppealStatus::Denied, 5).unwrap());
+        let decided = get_appeal(&conn, appeal.appeal_id).unwrap();
+        assert_eq!((decided.status.as_str(), decided.decided_by), ("accepted", Some(4)));
+
+        // an unban that failed puts it up for decision again
+        release_decision(&conn, appeal.appeal_id);
+        assert!(claim_decision(&conn, appeal.appeal_id, AppealStatus::Denied, 5).unwrap());
+    }
+}
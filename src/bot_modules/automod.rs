@@ -0,0 +1,572 @@
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use poise::{command, serenity_prelude::{self as serenity, Mentionable}};
+use rustrict::{Censor, Type};
+
+use crate::bot_modules::autoresponder::{author_roles, channel_locations};
+use crate::bot_modules::{moderation, modlog, warnings};
+use crate::guild_settings::{ensure_guild_settings, get_all_guild_settings, get_guild_settings};
+use crate::models::{AutomodExemption, AutomodSettingsChanges, GuildSettings, NewAutomodExemption};
+use crate::{Context, Data, Error};
+
+const AUTOMOD_COLOUR: u32 = 0xe67e22;
+
+/// A kind of content rustrict can detect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Category {
+    // Swearing.
+    #[name = "profanity"]
+    Profanity,
+    // Slurs and other hateful language.
+    #[name = "offensive"]
+    Offensive,
+    // Sexual language.
+    #[name = "sexual"]
+    Sexual,
+    // Insults and other mean language.
+    #[name = "mean"]
+    Mean,
+}
+
+impl Category {
+    const ALL: [Category; 4] = [Category::Profanity, Category::Offensive, Category::Sexual, Category::Mean];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Category::Profanity => "profanity",
+            Category::Offensive => "offensive",
+            Category::Sexual => "sexual",
+            Category::Mean => "mean",
+        }
+    }
+
+    fn rustrict_type(&self) -> Type {
+        match self {
+            Category::Profanity => Type::PROFANE,
+            Category::Offensive => Type::OFFENSIVE,
+            Category::Sexual => Type::SEXUAL,
+            Category::Mean => Type::MEAN,
+        }
+    }
+
+    /// The configured threshold of this category.
+    fn threshold<'a>(&self, settings: &'a GuildSettings) -> &'a str {
+        match self {
+            Category::Profanity => &settings.automod_profanity,
+            Category::Offensive => &settings.automod_offensive,
+            Category::Sexual => &settings.automod_sexual,
+            Category::Mean => &settings.automod_mean,
+        }
+    }
+}
+
+/// How bad a message has to be before automod acts on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Severity {
+    // Never act on this category.
+    #[name = "off"]
+    Off,
+    // Act on anything, even mild language.
+    #[name = "mild"]
+    Mild,
+    // Act on moderate and severe language.
+    #[name = "moderate"]
+    Moderate,
+    // Only act on severe language.
+    #[name = "severe"]
+    Severe,
+}
+
+impl Severity {
+    /// The name under which this severity is stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Off => "off",
+            Severity::Mild => "mild",
+            Severity::Moderate => "moderate",
+            Severity::Severe => "severe",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Severity::Off),
+            "mild" => Some(Severity::Mild),
+            "moderate" => Some(Severity::Moderate),
+            "severe" => Some(Severity::Severe),
+            _ => None,
+        }
+    }
+
+    pub fn rustrict_type(&self) -> Option<Type> {
+        match self {
+            Severity::Off => None,
+            Severity::Mild => Some(Type::MILD_OR_HIGHER),
+            Severity::Moderate => Some(Type::MODERATE_OR_HIGHER),
+            Severity::Severe => Some(Type::SEVERE),
+        }
+    }
+}
+
+/// What automod does with a message that crosses a threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Action {
+    // Delete the message.
+    #[name = "delete"]
+    Delete,
+    // Delete the message and warn its author in the channel.
+    #[name = "warn"]
+    Warn,
+    // Delete the message and time its author out.
+    #[name = "timeout"]
+    Timeout,
+    // Leave the message alone, only log it.
+    #[name = "log only"]
+    Log,
+}
+
+impl Action {
+    /// The name under which this action is stored in the database.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Action::Delete => "delete",
+            Action::Warn => "warn",
+            Action::Timeout => "timeout",
+            Action::Log => "log",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "delete" => Some(Action::Delete),
+            "warn" => Some(Action::Warn),
+            "timeout" => Some(Action::Timeout),
+            "log" => Some(Action::Log),
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Action::Delete => "deleted",
+            Action::Warn => "deleted and warned",
+            Action::Timeout => "deleted and timed out",
+            Action::Log => "logged",
+        }
+    }
+}
+
+/// The automod settings of a guild, ready to be checked against incoming messages.
+#[derive(Clone)]
+pub struct AutomodConfig {
+    pub thresholds: Vec<(Category, Type)>,
+    pub action: Action,
+    pub timeout_secs: i32,
+    pub exempt_roles: Vec<serenity::RoleId>,
+    pub exempt_locations: Vec<i64>,
+}
+
+impl AutomodConfig {
+    fn new(settings: &GuildSettings, exemptions: &[AutomodExemption]) -> Self {
+        let thresholds = Category::ALL
+            .iter()
+            .filter_map(|category| {
+                let severity = Severity::from_name(category.threshold(settings)).unwrap_or(Severity::Off);
+                severity.rustrict_type().map(|severity| (*category, category.rustrict_type() & severity))
+            })
+            .collect();
+
+        AutomodConfig {
+            thresholds,
+            action: Action::from_name(&settings.automod_action).unwrap_or(Action::Log),
+            timeout_secs: settings.automod_timeout_secs,
+            exempt_roles: exemptions.iter().filter(|e| e.is_role).map(|e| serenity::RoleId(e.target_id as u64)).collect(),
+            exempt_locations: exemptions.iter().filter(|e| !e.is_role).map(|e| e.target_id).collect(),
+        }
+    }
+
+    /// The first category the content crosses the threshold of.
+    pub fn classify(&self, content: &str) -> Option<Category> {
+        if self.thresholds.is_empty() {
+            return None;
+        }
+
+        let analysis = Censor::from_str(content).analyze();
+        self.thresholds
+            .iter()
+            .find(|(_, threshold)| analysis.is(*threshold))
+            .map(|(category, _)| *category)
+    }
+}
+
+fn get_exemptions(conn: &SqliteConnection) -> Vec<AutomodExemption> {
+    use crate::schema::automod_exemptions::dsl::*;
+
+    automod_exemptions
+        .order(exemption_id.asc())
+        .load(conn)
+        .expect("Error loading automod exemptions")
+}
+
+fn get_guild_exemptions(conn: &SqliteConnection, of_guild: i64) -> Vec<AutomodExemption> {
+    use crate::schema::automod_exemptions::dsl::*;
+
+    automod_exemptions
+        .filter(guild_id.eq(of_guild))
+        .order(exemption_id.asc())
+        .load(conn)
+        .expect("Error loading automod exemptions")
+}
+
+fn add_exemption(conn: &SqliteConnection, exemption: &NewAutomodExemption) -> bool {
+    use crate::schema::automod_exemptions;
+
+    let exists = get_guild_exemptions(conn, exemption.guild_id)
+        .iter()
+        .any(|e| e.target_id == exemption.target_id && e.is_role == exemption.is_role);
+    if exists {
+        return false;
+    }
+
+    diesel::insert_into(automod_exemptions::table)
+        .values(exemption)
+        .execute(conn)
+        .expect("Error adding automod exemption");
+
+    true
+}
+
+fn remove_exemption(conn: &SqliteConnection, of_guild: i64, of_target: i64, of_role: bool) -> bool {
+    use crate::schema::automod_exemptions::dsl::*;
+
+    let deleted = diesel::delete(automod_exemptions
+        .filter(guild_id.eq(of_guild))
+        .filter(target_id.eq(of_target))
+        .filter(is_role.eq(of_role)))
+        .execute(conn)
+        .expect("Error deleting automod exemption");
+
+    deleted > 0
+}
+
+fn update_automod_settings(conn: &SqliteConnection, of_guild: i64, changes: &AutomodSettingsChanges) {
+    use crate::schema::guild_settings::dsl::*;
+
+    ensure_guild_settings(conn, of_guild);
+    diesel::update(guild_settings.filter(guild_id.eq(of_guild)))
+        .set(changes)
+        .execute(conn)
+        .expect("Error updating guild settings");
+}
+
+/// Load the automod settings of all guilds that have it enabled.
+pub fn load_automod_configs(conn: &SqliteConnection) -> HashMap<serenity::GuildId, AutomodConfig> {
+    let mut exemptions: HashMap<i64, Vec<AutomodExemption>> = HashMap::new();
+    for exemption in get_exemptions(conn) {
+        exemptions.entry(exemption.guild_id).or_default().push(exemption);
+    }
+
+    get_all_guild_settings(conn)
+        .into_iter()
+        .filter(|settings| settings.automod_enabled)
+        .map(|settings| {
+            let guild_exemptions = exemptions.remove(&settings.guild_id).unwrap_or_default();
+            (serenity::GuildId(settings.guild_id as u64), AutomodConfig::new(&settings, &guild_exemptions))
+        })
+        .collect()
+}
+
+/// Reload the automod cache, so changes to the settings take effect immediately.
+pub async fn reload_automod_configs(data: &Data) {
+    let configs = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        load_automod_configs(&conn)
+    };
+    *data.automod.write().await = configs;
+}
+
+/// Check a message against the automod settings of its guild and act on it.
+///
+/// Returns whether the message was removed, in which case nothing else should respond to it.
+pub async fn handle_message(ctx: &serenity::Context, data: &Data, message: &serenity::Message) -> bool {
+    if message.author.bot {
+        return false;
+    }
+    let Some(guild_id) = message.guild_id else {
+        return false;
+    };
+
+    let (category, action, timeout_secs) = {
+        let configs = data.automod.read().await;
+        let Some(config) = configs.get(&guild_id) else {
+            return false;
+        };
+
+        let locations = channel_locations(ctx, message.channel_id);
+        if locations.iter().any(|l| config.exempt_locations.contains(l)) {
+            return false;
+        }
+        if author_roles(ctx, message).iter().any(|r| config.exempt_roles.contains(r)) {
+            return false;
+        }
+
+        let Some(category) = config.classify(&message.content) else {
+            return false;
+        };
+        (category, config.action, config.timeout_secs)
+    };
+
+    let mut outcome = action.describe().to_string();
+    if action != Action::Log {
+        data.message_cache.lock().await.skip_deletion_log(message.id);
+        if let Err(e) = message.delete(ctx).await {
+            warn!("Couldn't delete message flagged by automod: {}", e);
+            outcome = format!("not deleted ({})", e);
+        }
+    }
+    match action {
+        Action::Warn => {
+            let notice = format!("{}, please watch your language.", message.author.mention());
+            if let Err(e) = message.channel_id.say(ctx, notice).await {
+                warn!("Couldn't warn about message flagged by automod: {}", e);
+            }
+            let reason = format!("Automod: message flagged as {}", category.as_str());
+            let (warning, _) = warnings::issue_warning(ctx, data, guild_id, &message.author, ctx.cache.current_user_id(), &reason).await;
+            outcome = format!("{} (`#{}`)", outcome, warning.warning_id);
+        },
+        Action::Timeout => {
+            let reason = format!("Automod: message flagged as {}", category.as_str());
+            let result = moderation::apply_timeout(
+                ctx,
+                data,
+                guild_id,
+                &message.author,
+                ctx.cache.current_user_id(),
+                timeout_secs as i64,
+                &reason
+            ).await;
+            match result {
+                Ok(case) => outcome = format!("{} (case `#{}`)", outcome, case.case_id),
+                Err(e) => {
+                    warn!("Couldn't time out member flagged by automod: {}", e);
+                    outcome = format!("{}, but the timeout failed ({})", outcome, e);
+                },
+            }
+        },
+        _ => (),
+    }
+
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title("Automod")
+        .colour(AUTOMOD_COLOUR)
+        .description(format!(
+            "A message by {} ({}) in {} was flagged as {} and {}",
+            message.author.mention(),
+            message.author.tag(),
+            message.channel_id.mention(),
+            category.as_str(),
+            outcome
+        ))
+        .field("Content", modlog::truncate(&message.content, 1024), false)
+        .footer(|f| f.text(format!("User ID: {} | Message ID: {}", message.author.id, message.id)))
+        .timestamp(chrono::Utc::now());
+    modlog::post(ctx, data, guild_id, embed, None).await;
+
+    action != Action::Log
+}
+
+/// Configure automatic moderation of messages
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands(
+        "automod_settings",
+        "automod_toggle",
+        "automod_threshold",
+        "automod_action",
+        "automod_exempt_role",
+        "automod_unexempt_role",
+        "automod_exempt_channel",
+        "automod_unexempt_channel"
+    )
+)]
+pub async fn automod(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show the automod settings of this server
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "settings")]
+pub async fn automod_settings(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let (settings, exemptions) = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        (get_guild_settings(&conn, guild_id), get_guild_exemptions(&conn, guild_id))
+    };
+
+    let thresholds = Category::ALL
+        .iter()
+        .map(|c| format!("{}: {}", c.as_str(), c.threshold(&settings)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut action = settings.automod_action.clone();
+    if Action::from_name(&action) == Some(Action::Timeout) {
+        action.push_str(&format!(" for {} minutes", settings.automod_timeout_secs / 60));
+    }
+    let exemptions = exemptions
+        .iter()
+        .map(|e| if e.is_role {
+            serenity::RoleId(e.target_id as u64).mention().to_string()
+        } else {
+            serenity::ChannelId(e.target_id as u64).mention().to_string()
+        })
+        .collect::<Vec<_>>();
+
+    ctx.send(|cr| cr
+        .content(format!(
+            "Automod is **{}**.\nThresholds: {}\nAction: {}\nExempt: {}",
+            if settings.automod_enabled { "enabled" } else { "disabled" },
+            thresholds,
+            action,
+            if exemptions.is_empty() { "nothing".to_string() } else { exemptions.join(", ") }
+        ))
+        .allowed_mentions(|am| am.empty_parse())
+    ).await?;
+
+    Ok(())
+}
+
+/// Turn automod on or off
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "toggle")]
+pub async fn automod_toggle(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let enabled = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        let enabled = !get_guild_settings(&conn, guild_id).automod_enabled;
+        update_automod_settings(&conn, guild_id, &AutomodSettingsChanges {
+            automod_enabled: Some(enabled),
+            ..Default::default()
+        });
+        enabled
+    };
+    reload_automod_configs(ctx.data()).await;
+
+    ctx.say(format!("Automod is now {}.", if enabled { "enabled" } else { "disabled" })).await?;
+
+    Ok(())
+}
+
+/// Set how bad a message has to be before automod acts on it
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "threshold")]
+pub async fn automod_threshold(
+    ctx: Context<'_>,
+    #[description = "The kind of language"] category: Category,
+    #[description = "The lowest severity that is acted on"] severity: Severity,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let mut changes = AutomodSettingsChanges::default();
+    match category {
+        Category::Profanity => changes.automod_profanity = Some(severity.as_str()),
+        Category::Offensive => changes.automod_offensive = Some(severity.as_str()),
+        Category::Sexual => changes.automod_sexual = Some(severity.as_str()),
+        Category::Mean => changes.automod_mean = Some(severity.as_str()),
+    }
+    {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        update_automod_settings(&conn, guild_id, &changes);
+    }
+    reload_automod_configs(ctx.data()).await;
+
+    ctx.say(format!("The threshold for {} is now {}.", category.as_str(), severity.as_str())).await?;
+
+    Ok(())
+}
+
+/// Set what automod does with flagged messages
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "action")]
+pub async fn automod_action(
+    ctx: Context<'_>,
+    #[description = "What to do with flagged messages"] action: Action,
+    #[description = "How long timeouts last in minutes (default: 10)"] #[min = 1] #[max = 40320] timeout_minutes: Option<i32>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        update_automod_settings(&conn, guild_id, &AutomodSettingsChanges {
+            automod_action: Some(action.as_str()),
+            automod_timeout_secs: timeout_minutes.map(|m| m * 60),
+            ..Default::default()
+        });
+    }
+    reload_automod_configs(ctx.data()).await;
+
+    ctx.say(format!("Flagged messages are now {}.", action.describe())).await?;
+
+    Ok(())
+}
+
+async fn set_exemption(ctx: Context<'_>, target_id: u64, is_role: bool, exempt: bool, name: String) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let changed = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        if exempt {
+            add_exemption(&conn, &NewAutomodExemption { guild_id, target_id: target_id as i64, is_role })
+        } else {
+            remove_exemption(&conn, guild_id, target_id as i64, is_role)
+        }
+    };
+    reload_automod_configs(ctx.data()).await;
+
+    let reply = match (exempt, changed) {
+        (true, true) => format!("{} is now exempt from automod.", name),
+        (true, false) => format!("{} already is exempt from automod.", name),
+        (false, true) => format!("{} is no longer exempt from automod.", name),
+        (false, false) => format!("{} isn't exempt from automod.", name),
+    };
+    ctx.send(|cr| cr
+        .content(reply)
+        .allowed_mentions(|am| am.empty_parse())
+    ).await?;
+
+    Ok(())
+}
+
+/// Exempt members with a role from automod
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "exemptrole")]
+pub async fn automod_exempt_role(
+    ctx: Context<'_>,
+    #[description = "The role to exempt"] role: serenity::Role,
+) -> Result<(), Error> {
+    set_exemption(ctx, role.id.0, true, true, role.mention().to_string()).await
+}
+
+/// Stop exempting members with a role from automod
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "unexemptrole")]
+pub async fn automod_unexempt_role(
+    ctx: Context<'_>,
+    #[description = "The role to stop exempting"] role: serenity::Role,
+) -> Result<(), Error> {
+    set_exemption(ctx, role.id.0, true, false, role.mention().to_string()).await
+}
+
+/// Exempt a channel or category from automod
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "exemptchannel")]
+pub async fn automod_exempt_channel(
+    ctx: Context<'_>,
+    #[description = "The channel or category to exempt"] channel: serenity::GuildChannel,
+) -> Result<(), Error> {
+    set_exemption(ctx, channel.id.0, false, true, channel.mention().to_string()).await
+}
+
+/// Stop exempting a channel or category from automod
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "unexemptchannel")]
+pub async fn automod_unexempt_channel(
+    ctx: Context<'_>,
+    #[description = "The channel or category to stop exempting"] channel: serenity::GuildChannel,
+) -> Result<(), Error> {
+    set_exemption(ctx, channel.id.0, false, false, channel.mention().to_string()).await
+}
@@ -1,98 +1,1594 @@
-use poise::serenity_prelude::{self as serenity, Mentionable};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-use crate::{Data, Error};
+use chrono::{NaiveDate, Utc};
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use poise::{command, serenity_prelude::{self as serenity, Mentionable}};
+use rand::{seq::SliceRandom, Rng};
+use regex::{Captures, Regex, RegexBuilder};
 
-pub async fn handle_message(ctx: &serenity::Context, _: &poise::FrameworkContext<'_, Data, Error>, message: &serenity::Message) -> serenity::Result<()> {
-    if message.author.bot {
+use crate::models::{
+    AutoresponderChannelRule, Autoresponse, AutoresponseChanges, AutoresponseResponse, AutoresponseStat,
+    NewAutoresponderChannelRule, NewAutoresponse, NewAutoresponseResponse,
+};
+use crate::pagination::paginate;
+use crate::{Context, Data, Error};
+
+pub mod import_export;
+
+pub use import_export::{autoresponse_export, autoresponse_import};
+
+const AUTORESPONSES_PER_PAGE: usize = 10;
+const STATS_LIMIT: usize = 10;
+/// Text responses are sent as one message per part separated by this.
+const RESPONSE_SEPARATOR: &str = "{split}";
+/// How far back statistics can be looked at, ten years is plenty.
+const MAX_STATS_DAYS: i64 = 3650;
+
+lazy_static! {
+    static ref PLACEHOLDER_REGEX: Regex = Regex::new(r"\{(user|channel|count|mention:(\d+))\}").unwrap();
+}
+
+/// How the pattern of an autoresponse is compared against a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum MatchKind {
+    // The message has to be exactly the pattern.
+    #[name = "exact"]
+    Exact,
+    // The message has to start with the pattern.
+    #[name = "prefix"]
+    Prefix,
+    // The pattern has to appear anywhere in the message.
+    #[name = "contains"]
+    Contains,
+    // The pattern has to appear in the message, surrounded by anything but letters and digits.
+    #[name = "word"]
+    Word,
+    // The pattern is a regular expression that has to match somewhere in the message.
+    #[name = "regex"]
+    Regex,
+}
+
+impl MatchKind {
+    /// The name under which this match kind is stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MatchKind::Exact => "exact",
+            MatchKind::Prefix => "prefix",
+            MatchKind::Contains => "contains",
+            MatchKind::Word => "word",
+            MatchKind::Regex => "regex",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "exact" => Some(MatchKind::Exact),
+            "prefix" => Some(MatchKind::Prefix),
+            "contains" => Some(MatchKind::Contains),
+            "word" => Some(MatchKind::Word),
+            "regex" => Some(MatchKind::Regex),
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            MatchKind::Exact => "is exactly",
+            MatchKind::Prefix => "starts with",
+            MatchKind::Contains => "contains",
+            MatchKind::Word => "contains the word",
+            MatchKind::Regex => "matches the regex",
+        }
+    }
+}
+
+/// How an autoresponse responds once it fires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum ResponseKind {
+    // Reply to the message and ping its author.
+    #[name = "reply"]
+    Reply,
+    // Reply to the message without pinging its author.
+    #[name = "reply without ping"]
+    ReplyWithoutPing,
+    // Send a message into the channel without replying.
+    #[name = "message"]
+    Message,
+    // React to the message, the response is the emoji.
+    #[name = "reaction"]
+    Reaction,
+    // Send a sticker into the channel, the response is the sticker ID.
+    #[name = "sticker"]
+    Sticker,
+}
+
+impl ResponseKind {
+    /// The name under which this response kind is stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResponseKind::Reply => "reply",
+            ResponseKind::ReplyWithoutPing => "reply_without_ping",
+            ResponseKind::Message => "message",
+            ResponseKind::Reaction => "reaction",
+            ResponseKind::Sticker => "sticker",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "reply" => Some(ResponseKind::Reply),
+            "reply_without_ping" => Some(ResponseKind::ReplyWithoutPing),
+            "message" => Some(ResponseKind::Message),
+            "reaction" => Some(ResponseKind::Reaction),
+            "sticker" => Some(ResponseKind::Sticker),
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            ResponseKind::Reply => "replies",
+            ResponseKind::ReplyWithoutPing => "replies without ping",
+            ResponseKind::Message => "sends a message",
+            ResponseKind::Reaction => "reacts",
+            ResponseKind::Sticker => "sends a sticker",
+        }
+    }
+
+    /// Check whether a response can be sent this way, e.g. whether it's an emoji for reactions.
+    fn validate(&self, response: &str) -> Result<(), String> {
+        match self {
+            // anything that isn't a custom emoji is taken as a unicode emoji, so at least make sure it's a single "word"
+            ResponseKind::Reaction if response.is_empty() || response.contains(char::is_whitespace) => {
+                Err(format!("`{}` isn't an emoji", response))
+            },
+            ResponseKind::Reaction => serenity::ReactionType::try_from(response)
+                .map(|_| ())
+                .map_err(|_| format!("`{}` isn't an emoji", response)),
+            ResponseKind::Sticker => response
+                .parse::<u64>()
+                .map(|_| ())
+                .map_err(|_| format!("`{}` isn't a sticker ID", response)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether `word` appears in `content` without being part of a longer word.
+fn contains_word(content: &str, word: &str) -> bool {
+    if word.is_empty() {
+        return false;
+    }
+
+    content.match_indices(word).any(|(start, _)| {
+        let before = content[..start].chars().next_back();
+        let after = content[start + word.len()..].chars().next();
+        !before.map_or(false, is_word_char) && !after.map_or(false, is_word_char)
+    })
+}
+
+/// An enabled autoresponse, ready to be matched against incoming messages.
+#[derive(Clone)]
+pub struct Trigger {
+    pub autoresponse: Autoresponse,
+    pub kind: MatchKind,
+    pub response_kind: ResponseKind,
+    pub responses: Vec<String>,
+    pub allowed_roles: Vec<serenity::RoleId>,
+    pub channel_rules: Vec<AutoresponderChannelRule>,
+    regex: Option<Regex>,
+}
+
+impl Trigger {
+    /// Prepare an autoresponse for matching, compiling its pattern if it's a regex.
+    ///
+    /// Fails if the match or response kind is unknown, the regex is invalid or one of the responses
+    /// can't be sent with the response kind.
+    pub fn new(autoresponse: Autoresponse, responses: Vec<String>) -> Result<Self, String> {
+        let kind = MatchKind::from_name(&autoresponse.match_kind)
+            .ok_or_else(|| format!("unknown match kind `{}`", autoresponse.match_kind))?;
+        let response_kind = ResponseKind::from_name(&autoresponse.response_kind)
+            .ok_or_else(|| format!("unknown response kind `{}`", autoresponse.response_kind))?;
+        for response in &responses {
+            response_kind.validate(response)?;
+        }
+        let regex = if kind == MatchKind::Regex {
+            let regex = RegexBuilder::new(&autoresponse.pattern)
+                .case_insensitive(!autoresponse.case_sensitive)
+                .build()
+                .map_err(|e| e.to_string())?;
+            Some(regex)
+        } else {
+            None
+        };
+
+        let allowed_roles = parse_role_ids(&autoresponse.allowed_role_ids);
+
+        Ok(Trigger { autoresponse, kind, response_kind, responses, allowed_roles, channel_rules: Vec::new(), regex })
+    }
+
+    pub fn matches(&self, content: &str) -> bool {
+        if let Some(regex) = &self.regex {
+            return regex.is_match(content);
+        }
+
+        let (content, pattern): (Cow<str>, Cow<str>) = if self.autoresponse.case_sensitive {
+            (content.into(), self.autoresponse.pattern.as_str().into())
+        } else {
+            (content.to_lowercase().into(), self.autoresponse.pattern.to_lowercase().into())
+        };
+
+        match self.kind {
+            MatchKind::Exact => content == pattern,
+            MatchKind::Prefix => content.starts_with(&pattern),
+            MatchKind::Contains => content.contains(&pattern),
+            MatchKind::Word => contains_word(&content, &pattern),
+            MatchKind::Regex => false,
+        }
+    }
+
+    /// Whether someone with the given roles may trigger this autoresponse.
+    pub fn allows(&self, roles: &[serenity::RoleId]) -> bool {
+        if self.allowed_roles.is_empty() {
+            // moderator only autoresponses that haven't been converted to role IDs yet are for nobody
+            return !self.autoresponse.moderator_only;
+        }
+
+        self.allowed_roles.iter().any(|r| roles.contains(r))
+    }
+}
+
+/// The channel followed by its parents (e.g. the category), as far as the cache knows them.
+pub fn channel_locations(ctx: &serenity::Context, channel_id: serenity::ChannelId) -> Vec<i64> {
+    let mut locations = vec![channel_id.0 as i64];
+    let mut current = channel_id;
+    while let Some(parent_id) = ctx.cache.guild_channel(current).and_then(|c| c.parent_id) {
+        locations.push(parent_id.0 as i64);
+        current = parent_id;
+    }
+
+    locations
+}
+
+/// Whether the channel rules of a guild let autoresponses fire in any of the given locations.
+///
+/// A deny rule for any of the locations always wins. If there are allow rules, one of them has to
+/// match as well.
+fn channel_permitted(rules: &[AutoresponderChannelRule], guild_id: i64, locations: &[i64]) -> bool {
+    let mut has_allow_rules = false;
+    let mut allowed = false;
+    for rule in rules.iter().filter(|r| r.guild_id == guild_id) {
+        let hit = locations.contains(&rule.channel_id);
+        if rule.allow {
+            has_allow_rules = true;
+            allowed |= hit;
+        } else if hit {
+            return false;
+        }
+    }
+
+    !has_allow_rules || allowed
+}
+
+/// Where and by whom a message was sent, everything that decides whether a matching trigger fires.
+struct Origin<'a> {
+    guild_id: i64,
+    channel_id: serenity::ChannelId,
+    user_id: serenity::UserId,
+    locations: &'a [i64],
+    roles: &'a [serenity::RoleId],
+}
+
+/// Whether a matching trigger fires, apart from its chance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Check {
+    Fires,
+    MissingRole,
+    ChannelDenied,
+    CoolingDown,
+}
+
+impl Check {
+    fn describe(&self) -> &'static str {
+        match self {
+            Check::Fires => "fires",
+            Check::MissingRole => "skipped, the author doesn't have one of the allowed roles",
+            Check::ChannelDenied => "skipped, it isn't allowed in this channel",
+            Check::CoolingDown => "skipped, it's still cooling down",
+        }
+    }
+}
+
+fn check_trigger(trigger: &Trigger, origin: &Origin, cooldowns: &Cooldowns, now: Instant) -> Check {
+    if !trigger.allows(origin.roles) {
+        return Check::MissingRole;
+    }
+    if !channel_permitted(&trigger.channel_rules, origin.guild_id, origin.locations) {
+        return Check::ChannelDenied;
+    }
+    if cooldowns.is_cooling_down(trigger.autoresponse.autoresponse_id, origin.channel_id, origin.user_id, now) {
+        return Check::CoolingDown;
+    }
+
+    Check::Fires
+}
+
+fn parse_role_ids(role_ids: &str) -> Vec<serenity::RoleId> {
+    role_ids
+        .split(',')
+        .filter_map(|id| id.trim().parse::<u64>().ok())
+        .map(serenity::RoleId)
+        .collect()
+}
+
+fn join_role_ids(role_ids: &[serenity::RoleId]) -> String {
+    role_ids.iter().map(|r| r.0.to_string()).collect::<Vec<_>>().join(",")
+}
+
+/// When the autoresponses can fire again, per channel and per user.
+#[derive(Default)]
+pub struct Cooldowns {
+    channels: HashMap<(i32, serenity::ChannelId), Instant>,
+    users: HashMap<(i32, serenity::UserId), Instant>,
+}
+
+impl Cooldowns {
+    fn is_cooling_down(&self, autoresponse_id: i32, channel_id: serenity::ChannelId, user_id: serenity::UserId, now: Instant) -> bool {
+        let channel_until = self.channels.get(&(autoresponse_id, channel_id));
+        let user_until = self.users.get(&(autoresponse_id, user_id));
+
+        channel_until.map_or(false, |until| *until > now) || user_until.map_or(false, |until| *until > now)
+    }
+
+    fn start(&mut self, autoresponse: &Autoresponse, channel_id: serenity::ChannelId, user_id: serenity::UserId, now: Instant) {
+        // forget about everything that ran out, so the maps don't grow forever
+        self.channels.retain(|_, until| *until > now);
+        self.users.retain(|_, until| *until > now);
+
+        if autoresponse.channel_cooldown_secs > 0 {
+            let until = now + Duration::from_secs(autoresponse.channel_cooldown_secs as u64);
+            self.channels.insert((autoresponse.autoresponse_id, channel_id), until);
+        }
+        if autoresponse.user_cooldown_secs > 0 {
+            let until = now + Duration::from_secs(autoresponse.user_cooldown_secs as u64);
+            self.users.insert((autoresponse.autoresponse_id, user_id), until);
+        }
+    }
+}
+
+fn roll_chance(chance_percent: i32) -> bool {
+    chance_percent >= 100 || rand::thread_rng().gen_range(0..100) < chance_percent
+}
+
+/// Fill in the placeholders of a response.
+///
+/// Supported are `{user}` and `{channel}` for mentions of the author and the channel, `{mention:ID}`
+/// to mention any user and `{count}` for how often the autoresponse has been triggered. `{split}` is
+/// left alone, it's handled when the response is sent.
+fn render_response(template: &str, user_id: serenity::UserId, channel_id: serenity::ChannelId, count: i32) -> String {
+    PLACEHOLDER_REGEX.replace_all(template, |caps: &Captures| match &caps[1] {
+        "user" => user_id.mention().to_string(),
+        "channel" => channel_id.mention().to_string(),
+        "count" => count.to_string(),
+        _ => format!("<@{}>", &caps[2]),
+    }).into_owned()
+}
+
+fn get_enabled_autoresponses(conn: &SqliteConnection) -> Vec<Autoresponse> {
+    use crate::schema::autoresponses::dsl::*;
+
+    autoresponses
+        .filter(enabled.eq(true))
+        .order(autoresponse_id.asc())
+        .load(conn)
+        .expect("Error loading autoresponses")
+}
+
+fn get_all_responses(conn: &SqliteConnection) -> Vec<AutoresponseResponse> {
+    use crate::schema::autoresponse_responses::dsl::*;
+
+    autoresponse_responses
+        .order(response_id.asc())
+        .load(conn)
+        .expect("Error loading autoresponse responses")
+}
+
+fn get_responses(conn: &SqliteConnection, of: i32) -> Vec<AutoresponseResponse> {
+    use crate::schema::autoresponse_responses::dsl::*;
+
+    autoresponse_responses
+        .filter(autoresponse_id.eq(of))
+        .order(response_id.asc())
+        .load(conn)
+        .expect("Error loading autoresponse responses")
+}
+
+fn add_response(conn: &SqliteConnection, to: i32, response: &str) -> QueryResult<()> {
+    use crate::schema::autoresponse_responses;
+
+    diesel::insert_into(autoresponse_responses::table)
+        .values(&NewAutoresponseResponse {
+            autoresponse_id: to,
+            response,
+        })
+        .execute(conn)
+        .map(|_| ())
+}
+
+fn delete_response(conn: &SqliteConnection, to_delete: i32) {
+    use crate::schema::autoresponse_responses::dsl::*;
+
+    diesel::delete(autoresponse_responses.filter(response_id.eq(to_delete)))
+        .execute(conn)
+        .expect("Error deleting autoresponse response");
+}
+
+fn set_allowed_roles(conn: &SqliteConnection, to_update: i32, roles: &[serenity::RoleId]) {
+    use crate::schema::autoresponses::dsl::*;
+
+    diesel::update(autoresponses.filter(autoresponse_id.eq(to_update)))
+        .set((allowed_role_ids.eq(join_role_ids(roles)), moderator_only.eq(false)))
+        .execute(conn)
+        .expect("Error updating autoresponse");
+}
+
+fn get_moderator_only_autoresponses(conn: &SqliteConnection) -> Vec<Autoresponse> {
+    use crate::schema::autoresponses::dsl::*;
+
+    autoresponses
+        .filter(moderator_only.eq(true))
+        .load(conn)
+        .expect("Error loading autoresponses")
+}
+
+fn get_channel_rules(conn: &SqliteConnection) -> Vec<AutoresponderChannelRule> {
+    use crate::schema::autoresponder_channel_rules::dsl::*;
+
+    autoresponder_channel_rules
+        .order(rule_id.asc())
+        .load(conn)
+        .expect("Error loading autoresponder channel rules")
+}
+
+fn get_guild_channel_rules(conn: &SqliteConnection, of_guild: i64) -> Vec<AutoresponderChannelRule> {
+    use crate::schema::autoresponder_channel_rules::dsl::*;
+
+    autoresponder_channel_rules
+        .filter(guild_id.eq(of_guild))
+        .order(rule_id.asc())
+        .load(conn)
+        .expect("Error loading autoresponder channel rules")
+}
+
+/// Remove the rule for a channel, either the guild wide one or the one of a single autoresponse.
+fn clear_channel_rule(conn: &SqliteConnection, of_guild: i64, of_autoresponse: Option<i32>, of_channel: i64) -> bool {
+    use crate::schema::autoresponder_channel_rules::dsl::*;
+
+    let to_delete: Vec<i32> = get_guild_channel_rules(conn, of_guild)
+        .into_iter()
+        .filter(|r| r.autoresponse_id == of_autoresponse && r.channel_id == of_channel)
+        .map(|r| r.rule_id)
+        .collect();
+    if to_delete.is_empty() {
+        return false;
+    }
+
+    diesel::delete(autoresponder_channel_rules.filter(rule_id.eq_any(to_delete)))
+        .execute(conn)
+        .expect("Error deleting autoresponder channel rule");
+
+    true
+}
+
+fn set_channel_rule(conn: &SqliteConnection, rule: &NewAutoresponderChannelRule) {
+    use crate::schema::autoresponder_channel_rules;
+
+    clear_channel_rule(conn, rule.guild_id, rule.autoresponse_id, rule.channel_id);
+    diesel::insert_into(autoresponder_channel_rules::table)
+        .values(rule)
+        .execute(conn)
+        .expect("Error inserting autoresponder channel rule");
+}
+
+/// Count another trigger of an autoresponse and return the new count.
+fn increment_trigger_count(conn: &SqliteConnection, to_update: i32) -> i32 {
+    use crate::schema::autoresponses::dsl::*;
+
+    diesel::update(autoresponses.filter(autoresponse_id.eq(to_update)))
+        .set(trigger_count.eq(trigger_count + 1))
+        .execute(conn)
+        .expect("Error updating autoresponse");
+
+    autoresponses
+        .filter(autoresponse_id.eq(to_update))
+        .select(trigger_count)
+        .first(conn)
+        .unwrap_or_default()
+}
+
+/// Count a trigger of an autoresponse in the statistics of the channel for today.
+fn record_stat(conn: &SqliteConnection, of: i32, in_guild: i64, in_channel: i64) {
+    use crate::schema::autoresponse_stats::dsl::*;
+
+    let today = Utc::now().naive_utc().date();
+    let updated = diesel::update(autoresponse_stats
+            .filter(autoresponse_id.eq(of))
+            .filter(guild_id.eq(in_guild))
+            .filter(channel_id.eq(in_channel))
+            .filter(day.eq(today)))
+        .set(count.eq(count + 1))
+        .execute(conn)
+        .expect("Error updating autoresponse stats");
+
+    if updated == 0 {
+        diesel::insert_into(autoresponse_stats)
+            .values(&AutoresponseStat {
+                autoresponse_id: of,
+                guild_id: in_guild,
+                channel_id: in_channel,
+                day: today,
+                count: 1,
+            })
+            .execute(conn)
+            .expect("Error inserting autoresponse stats");
+    }
+}
+
+fn get_stats(conn: &SqliteConnection, of_guild: i64, since: NaiveDate) -> Vec<AutoresponseStat> {
+    use crate::schema::autoresponse_stats::dsl::*;
+
+    autoresponse_stats
+        .filter(guild_id.eq(of_guild))
+        .filter(day.ge(since))
+        .load(conn)
+        .expect("Error loading autoresponse stats")
+}
+
+/// Load all enabled autoresponses from the database, in the order they should be checked.
+///
+/// Autoresponses that can't be prepared for matching (e.g. because of a broken regex) or that have
+/// nothing to respond with are skipped.
+pub fn load_triggers(conn: &SqliteConnection) -> Vec<Trigger> {
+    let mut responses: HashMap<i32, Vec<String>> = HashMap::new();
+    for response in get_all_responses(conn) {
+        responses.entry(response.autoresponse_id).or_default().push(response.response);
+    }
+    let mut channel_rules: HashMap<i32, Vec<AutoresponderChannelRule>> = HashMap::new();
+    for rule in get_channel_rules(conn) {
+        if let Some(id) = rule.autoresponse_id {
+            channel_rules.entry(id).or_default().push(rule);
+        }
+    }
+
+    get_enabled_autoresponses(conn)
+        .into_iter()
+        .filter_map(|autoresponse| {
+            let id = autoresponse.autoresponse_id;
+            let Some(responses) = responses.remove(&id) else {
+                warn!("Skipping autoresponse {}: it has no responses", id);
+                return None;
+            };
+
+            match Trigger::new(autoresponse, responses) {
+                Ok(trigger) => Some(Trigger {
+                    channel_rules: channel_rules.remove(&id).unwrap_or_default(),
+                    ..trigger
+                }),
+                Err(e) => {
+                    warn!("Skipping autoresponse {}: {}", id, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Load the channel rules that apply to all autoresponses of a guild.
+pub fn load_guild_channel_rules(conn: &SqliteConnection) -> Vec<AutoresponderChannelRule> {
+    get_channel_rules(conn)
+        .into_iter()
+        .filter(|r| r.autoresponse_id.is_none())
+        .collect()
+}
+
+/// Restrict autoresponses that used to be for moderators only to the roles called "Moderator".
+///
+/// Those autoresponses used to look up the role by name on every message, this needs the roles of
+/// all guilds in the cache, so it has to run once the cache is ready.
+pub async fn convert_moderator_only(ctx: &serenity::Context, data: &Data) {
+    let moderator_roles: Vec<serenity::RoleId> = ctx.cache.guilds()
+        .into_iter()
+        .filter_map(|guild_id| ctx.cache.guild(guild_id))
+        .flat_map(|guild| guild.roles.into_values().filter(|r| r.name == "Moderator").map(|r| r.id))
+        .collect();
+
+    let converted = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        let legacy = get_moderator_only_autoresponses(&conn);
+        if legacy.is_empty() {
+            return;
+        }
+        if moderator_roles.is_empty() {
+            warn!("Couldn't find a Moderator role, {} moderator only autoresponses stay disabled", legacy.len());
+            return;
+        }
+
+        for autoresponse in &legacy {
+            let mut roles = parse_role_ids(&autoresponse.allowed_role_ids);
+            for role in &moderator_roles {
+                if !roles.contains(role) {
+                    roles.push(*role);
+                }
+            }
+            set_allowed_roles(&conn, autoresponse.autoresponse_id, &roles);
+        }
+        legacy.len()
+    };
+
+    info!("Restricted {} moderator only autoresponses to the Moderator roles", converted);
+    reload_triggers(data).await;
+}
+
+/// Reload the trigger cache, so changes to the autoresponses take effect immediately.
+pub async fn reload_triggers(data: &Data) {
+    let conn = data.pool.get().expect("Couldn't get connection from pool");
+    let triggers = load_triggers(&conn);
+    let guild_channel_rules = load_guild_channel_rules(&conn);
+    *data.autoresponses.write().await = triggers;
+    *data.autoresponder_channel_rules.write().await = guild_channel_rules;
+}
+
+fn get_autoresponse(conn: &SqliteConnection, to_get: i32) -> Option<Autoresponse> {
+    use crate::schema::autoresponses::dsl::*;
+
+    autoresponses.filter(autoresponse_id.eq(to_get)).first(conn).ok()
+}
+
+fn get_all_autoresponses(conn: &SqliteConnection) -> Vec<Autoresponse> {
+    use crate::schema::autoresponses::dsl::*;
+
+    autoresponses
+        .order(autoresponse_id.asc())
+        .load(conn)
+        .expect("Error loading autoresponses")
+}
+
+fn create_autoresponse(conn: &SqliteConnection, new_autoresponse: &NewAutoresponse) -> QueryResult<Autoresponse> {
+    use crate::schema::autoresponses::dsl::*;
+
+    diesel::insert_into(autoresponses)
+        .values(new_autoresponse)
+        .execute(conn)?;
+
+    autoresponses.order(autoresponse_id.desc()).first(conn)
+}
+
+fn update_autoresponse(conn: &SqliteConnection, to_update: i32, changes: &AutoresponseChanges) -> Option<Autoresponse> {
+    use crate::schema::autoresponses::dsl::*;
+
+    diesel::update(autoresponses.filter(autoresponse_id.eq(to_update)))
+        .set(changes)
+        .execute(conn)
+        .expect("Error updating autoresponse");
+
+    get_autoresponse(conn, to_update)
+}
+
+fn set_autoresponse_enabled(conn: &SqliteConnection, to_update: i32, is_enabled: bool) -> QueryResult<()> {
+    use crate::schema::autoresponses::dsl::*;
+
+    diesel::update(autoresponses.filter(autoresponse_id.eq(to_update)))
+        .set(enabled.eq(is_enabled))
+        .execute(conn)
+        .map(|_| ())
+}
+
+fn delete_autoresponse(conn: &SqliteConnection, to_delete: i32) -> QueryResult<bool> {
+    use crate::schema::autoresponses::dsl::*;
+
+    let deleted = diesel::delete(autoresponses.filter(autoresponse_id.eq(to_delete)))
+        .execute(conn)?;
+
+    Ok(deleted > 0)
+}
+
+/// A one line summary of when an autoresponse fires.
+fn describe_autoresponse(autoresponse: &Autoresponse) -> String {
+    let condition = MatchKind::from_name(&autoresponse.match_kind)
+        .map(|kind| kind.describe())
+        .unwrap_or("(unknown match kind)");
+
+    let mut description = format!(
+        "`#{}` message {} `{}`",
+        autoresponse.autoresponse_id,
+        condition,
+        autoresponse.pattern
+    );
+    if !autoresponse.case_sensitive {
+        description.push_str(" (ignoring case)");
+    }
+    if autoresponse.channel_cooldown_secs > 0 {
+        description.push_str(&format!(" (cooldown {}s per channel)", autoresponse.channel_cooldown_secs));
+    }
+    if autoresponse.user_cooldown_secs > 0 {
+        description.push_str(&format!(" (cooldown {}s per user)", autoresponse.user_cooldown_secs));
+    }
+    if autoresponse.chance_percent < 100 {
+        description.push_str(&format!(" ({}% chance)", autoresponse.chance_percent));
+    }
+    let allowed_roles = parse_role_ids(&autoresponse.allowed_role_ids);
+    if !allowed_roles.is_empty() {
+        let roles = allowed_roles.iter().map(|r| r.mention().to_string()).collect::<Vec<_>>().join(", ");
+        description.push_str(&format!(" [only for {}]", roles));
+    } else if autoresponse.moderator_only {
+        description.push_str(" [moderators only]");
+    }
+    if let Some(response_kind) = ResponseKind::from_name(&autoresponse.response_kind) {
+        description.push_str(&format!(", {}", response_kind.describe()));
+    }
+    description.push_str(&format!(" - triggered {} times", autoresponse.trigger_count));
+    if !autoresponse.enabled {
+        description.push_str(" [disabled]");
+    }
+
+    description
+}
+
+/// Show what an autoresponse reacts to and what it would reply with, without pinging anyone.
+async fn send_preview(ctx: Context<'_>, header: &str, autoresponse: &Autoresponse, responses: &[AutoresponseResponse]) -> Result<(), Error> {
+    let previews = responses
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let preview = match ResponseKind::from_name(&autoresponse.response_kind) {
+                Some(ResponseKind::Reaction) => format!("reaction {}", r.response),
+                Some(ResponseKind::Sticker) => format!("sticker `{}`", r.response),
+                _ => render_response(&r.response, ctx.author().id, ctx.channel_id(), autoresponse.trigger_count + 1),
+            };
+            format!("**{}.** {}", i + 1, preview)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    ctx.send(|cr| cr
+        .content(format!(
+            "{}\n{}\n\nPreview of the {}:\n{}",
+            header,
+            describe_autoresponse(autoresponse),
+            if responses.len() == 1 { "response" } else { "responses, one of which is picked at random" },
+            previews
+        ))
+        .allowed_mentions(|am| am.empty_parse())
+    ).await?;
+
+    Ok(())
+}
+
+/// Manage the autoresponder
+#[command(
+    slash_command,
+    prefix_command,
+    required_permissions = "MANAGE_MESSAGES",
+    subcommands(
+        "autoresponse_add",
+        "autoresponse_remove",
+        "autoresponse_list",
+        "autoresponse_edit",
+        "autoresponse_toggle",
+        "autoresponse_add_response",
+        "autoresponse_remove_response",
+        "autoresponse_allow_role",
+        "autoresponse_disallow_role",
+        "autoresponse_allow_channel",
+        "autoresponse_deny_channel",
+        "autoresponse_clear_channel",
+        "autoresponse_channels",
+        "autoresponse_stats",
+        "autoresponse_test",
+        "autoresponse_export",
+        "autoresponse_import"
+    )
+)]
+pub async fn autoresponse(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Add a new autoresponse
+#[command(slash_command, prefix_command, required_permissions = "MANAGE_MESSAGES", rename = "add")]
+pub async fn autoresponse_add(
+    ctx: Context<'_>,
+    #[description = "How the pattern is matched"] match_kind: MatchKind,
+    #[description = "The text that triggers the response"] pattern: String,
+    #[description = "What to reply with, may contain {user}, {channel}, {mention:ID}, {count} and {split}"] response: String,
+    #[description = "Whether upper and lower case have to match"] case_sensitive: bool,
+    #[description = "Only members with this role can trigger it (default: everyone)"] role: Option<serenity::Role>,
+    #[description = "How to respond (default: reply)"] response_kind: Option<ResponseKind>,
+    #[description = "Seconds before it can fire again in the same channel (default: 0)"] #[min = 0] channel_cooldown: Option<i32>,
+    #[description = "Seconds before the same user can trigger it again (default: 0)"] #[min = 0] user_cooldown: Option<i32>,
+    #[description = "Chance in percent that it fires when matched (default: 100)"] #[min = 1] #[max = 100] chance: Option<i32>,
+) -> Result<(), Error> {
+    if match_kind == MatchKind::Regex {
+        if let Err(e) = Regex::new(&pattern) {
+            ctx.say(format!("That's not a valid regex:\n```\n{}\n```", e)).await?;
+            return Ok(());
+        }
+    }
+    let response_kind = response_kind.unwrap_or(ResponseKind::Reply);
+    if let Err(e) = response_kind.validate(&response) {
+        ctx.say(format!("That response doesn't work: {}", e)).await?;
         return Ok(());
     }
 
-    if message.guild_id.is_none() {
+    let data = ctx.data();
+    let (autoresponse, responses) = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        let autoresponse = create_autoresponse(&conn, &NewAutoresponse {
+            pattern: &pattern,
+            match_kind: match_kind.as_str(),
+            case_sensitive,
+            allowed_role_ids: &role.map(|r| r.id.0.to_string()).unwrap_or_default(),
+            response_kind: response_kind.as_str(),
+            channel_cooldown_secs: channel_cooldown.unwrap_or(0),
+            user_cooldown_secs: user_cooldown.unwrap_or(0),
+            chance_percent: chance.unwrap_or(100),
+        }).expect("Error creating autoresponse");
+        add_response(&conn, autoresponse.autoresponse_id, &response).expect("Error adding autoresponse response");
+        let responses = get_responses(&conn, autoresponse.autoresponse_id);
+        (autoresponse, responses)
+    };
+    reload_triggers(data).await;
+
+    send_preview(ctx, "Autoresponse added!", &autoresponse, &responses).await
+}
+
+/// Remove an autoresponse
+#[command(slash_command, prefix_command, required_permissions = "MANAGE_MESSAGES", rename = "remove")]
+pub async fn autoresponse_remove(
+    ctx: Context<'_>,
+    #[description = "The autoresponse to remove"] autoresponse_id: i32,
+) -> Result<(), Error> {
+    let data = ctx.data();
+    let existed = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        delete_autoresponse(&conn, autoresponse_id).expect("Error deleting autoresponse")
+    };
+
+    if existed {
+        reload_triggers(data).await;
+        ctx.say("Successfully removed autoresponse!").await?;
+    } else {
+        ctx.say("Couldn't find autoresponse with that ID!").await?;
+    }
+
+    Ok(())
+}
+
+/// List all autoresponses
+#[command(slash_command, prefix_command, required_permissions = "MANAGE_MESSAGES", rename = "list")]
+pub async fn autoresponse_list(ctx: Context<'_>) -> Result<(), Error> {
+    let all = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        get_all_autoresponses(&conn)
+    };
+
+    if all.is_empty() {
+        ctx.say("There are no autoresponses yet!").await?;
         return Ok(());
     }
 
-    if message.content == "wkjfneasdf" {
-        message.reply_ping(ctx, "That's not a word, but rather an oddly specific keyboard mash. Good job!").await?;
+    let pages = all
+        .chunks(AUTORESPONSES_PER_PAGE)
+        .map(|chunk| chunk.iter().map(describe_autoresponse).collect::<Vec<_>>().join("\n"))
+        .collect::<Vec<_>>();
+
+    paginate(ctx, "Autoresponses", &pages, false).await
+}
+
+/// Change an existing autoresponse
+#[command(slash_command, prefix_command, required_permissions = "MANAGE_MESSAGES", rename = "edit")]
+pub async fn autoresponse_edit(
+    ctx: Context<'_>,
+    #[description = "The autoresponse to change"] autoresponse_id: i32,
+    #[description = "How the pattern is matched"] match_kind: Option<MatchKind>,
+    #[description = "The text that triggers the response"] pattern: Option<String>,
+    #[description = "Whether upper and lower case have to match"] case_sensitive: Option<bool>,
+    #[description = "Seconds before it can fire again in the same channel"] #[min = 0] channel_cooldown: Option<i32>,
+    #[description = "Seconds before the same user can trigger it again"] #[min = 0] user_cooldown: Option<i32>,
+    #[description = "Chance in percent that it fires when matched"] #[min = 1] #[max = 100] chance: Option<i32>,
+    #[description = "How to respond"] response_kind: Option<ResponseKind>,
+) -> Result<(), Error> {
+    let changes = AutoresponseChanges {
+        pattern: pattern.as_deref(),
+        match_kind: match_kind.map(|k| k.as_str()),
+        case_sensitive,
+        channel_cooldown_secs: channel_cooldown,
+        user_cooldown_secs: user_cooldown,
+        chance_percent: chance,
+        response_kind: response_kind.map(|k| k.as_str()),
+    };
+    if changes.pattern.is_none()
+        && changes.match_kind.is_none()
+        && changes.case_sensitive.is_none()
+        && changes.channel_cooldown_secs.is_none()
+        && changes.user_cooldown_secs.is_none()
+        && changes.chance_percent.is_none()
+        && changes.response_kind.is_none()
+    {
+        ctx.say("You didn't specify anything to change!").await?;
         return Ok(());
     }
 
-    // test == turing
-    if message.content == "test" {
-        message.reply_ping(ctx, "turing").await?;
+    let data = ctx.data();
+    let conn = data.pool.get().expect("Couldn't get connection from pool");
+    let Some(mut changed) = get_autoresponse(&conn, autoresponse_id) else {
+        ctx.say("Couldn't find autoresponse with that ID!").await?;
+        return Ok(());
+    };
+
+    // make sure the autoresponse still works with the changes applied before saving them
+    if let Some(pattern) = changes.pattern {
+        changed.pattern = pattern.to_string();
+    }
+    if let Some(match_kind) = changes.match_kind {
+        changed.match_kind = match_kind.to_string();
+    }
+    if let Some(case_sensitive) = changes.case_sensitive {
+        changed.case_sensitive = case_sensitive;
+    }
+    if let Some(response_kind) = changes.response_kind {
+        changed.response_kind = response_kind.to_string();
+    }
+    let responses = get_responses(&conn, autoresponse_id).into_iter().map(|r| r.response).collect();
+    if let Err(e) = Trigger::new(changed, responses) {
+        ctx.say(format!("That change would break the autoresponse:\n```\n{}\n```", e)).await?;
         return Ok(());
     }
 
-    // F
-    if message.content.to_lowercase().starts_with("f ") || message.content.to_lowercase() == "f"  {
-        message.reply_ping(ctx, ":regional_indicator_f:").await?;
+    let Some(updated) = update_autoresponse(&conn, autoresponse_id, &changes) else {
+        ctx.say("Couldn't find autoresponse with that ID!").await?;
         return Ok(());
+    };
+    let responses = get_responses(&conn, autoresponse_id);
+    reload_triggers(data).await;
+
+    send_preview(ctx, "Autoresponse updated!", &updated, &responses).await
+}
+
+/// Enable or disable an autoresponse
+#[command(slash_command, prefix_command, required_permissions = "MANAGE_MESSAGES", rename = "toggle")]
+pub async fn autoresponse_toggle(
+    ctx: Context<'_>,
+    #[description = "The autoresponse to enable or disable"] autoresponse_id: i32,
+) -> Result<(), Error> {
+    let data = ctx.data();
+    let toggled = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        get_autoresponse(&conn, autoresponse_id).map(|autoresponse| {
+            set_autoresponse_enabled(&conn, autoresponse_id, !autoresponse.enabled).expect("Error updating autoresponse");
+            !autoresponse.enabled
+        })
+    };
+
+    match toggled {
+        Some(now_enabled) => {
+            reload_triggers(data).await;
+            ctx.say(format!(
+                "Autoresponse `#{}` is now {}!",
+                autoresponse_id,
+                if now_enabled { "enabled" } else { "disabled" }
+            )).await?;
+        },
+        None => {
+            ctx.say("Couldn't find autoresponse with that ID!").await?;
+        },
     }
 
-    // ^^
-    if message.content.starts_with("^^ ") || message.content == "^^" {
-        message.reply_ping(ctx, "^u^").await?;
+    Ok(())
+}
+
+/// Add another possible response to an autoresponse
+#[command(slash_command, prefix_command, required_permissions = "MANAGE_MESSAGES", rename = "addresponse")]
+pub async fn autoresponse_add_response(
+    ctx: Context<'_>,
+    #[description = "The autoresponse to add the response to"] autoresponse_id: i32,
+    #[description = "What to reply with, may contain {user}, {channel}, {mention:ID}, {count} and {split}"] response: String,
+) -> Result<(), Error> {
+    let data = ctx.data();
+    let conn = data.pool.get().expect("Couldn't get connection from pool");
+    let Some(autoresponse) = get_autoresponse(&conn, autoresponse_id) else {
+        ctx.say("Couldn't find autoresponse with that ID!").await?;
         return Ok(());
+    };
+
+    if let Some(response_kind) = ResponseKind::from_name(&autoresponse.response_kind) {
+        if let Err(e) = response_kind.validate(&response) {
+            ctx.say(format!("That response doesn't work: {}", e)).await?;
+            return Ok(());
+        }
     }
 
-    // \o/
-    if message.content.starts_with("\\o/") {
-        message.reply_ping(ctx, "https://imgur.com/qJYq4Xn").await?;
+    add_response(&conn, autoresponse_id, &response).expect("Error adding autoresponse response");
+    let responses = get_responses(&conn, autoresponse_id);
+    reload_triggers(data).await;
+
+    send_preview(ctx, "Response added!", &autoresponse, &responses).await
+}
+
+/// Remove one of the possible responses of an autoresponse
+#[command(slash_command, prefix_command, required_permissions = "MANAGE_MESSAGES", rename = "removeresponse")]
+pub async fn autoresponse_remove_response(
+    ctx: Context<'_>,
+    #[description = "The autoresponse to remove the response from"] autoresponse_id: i32,
+    #[description = "The number of the response, as shown in the preview"] #[min = 1] number: usize,
+) -> Result<(), Error> {
+    let data = ctx.data();
+    let conn = data.pool.get().expect("Couldn't get connection from pool");
+    let Some(autoresponse) = get_autoresponse(&conn, autoresponse_id) else {
+        ctx.say("Couldn't find autoresponse with that ID!").await?;
+        return Ok(());
+    };
+
+    let responses = get_responses(&conn, autoresponse_id);
+    let Some(to_delete) = number.checked_sub(1).and_then(|i| responses.get(i)) else {
+        ctx.say(format!("That autoresponse only has {} responses!", responses.len())).await?;
+        return Ok(());
+    };
+    if responses.len() == 1 {
+        ctx.say("That's the only response left! Remove the whole autoresponse instead.").await?;
         return Ok(());
     }
 
-    // OBAMA IS GONE
-    if message.content.to_lowercase().starts_with("crab") {
-        message.reply_ping(ctx, "https://tenor.com/view/crab-safe-dance-gif-13211112").await?;
+    delete_response(&conn, to_delete.response_id);
+    let responses = get_responses(&conn, autoresponse_id);
+    reload_triggers(data).await;
+
+    send_preview(ctx, "Response removed!", &autoresponse, &responses).await
+}
+
+/// Let members with a role trigger an autoresponse
+///
+/// Once an autoresponse has roles, only members with at least one of them can trigger it.
+#[command(slash_command, prefix_command, required_permissions = "MANAGE_MESSAGES", rename = "allowrole")]
+pub async fn autoresponse_allow_role(
+    ctx: Context<'_>,
+    #[description = "The autoresponse to restrict"] autoresponse_id: i32,
+    #[description = "The role which can trigger it"] role: serenity::Role,
+) -> Result<(), Error> {
+    let data = ctx.data();
+    let conn = data.pool.get().expect("Couldn't get connection from pool");
+    let Some(autoresponse) = get_autoresponse(&conn, autoresponse_id) else {
+        ctx.say("Couldn't find autoresponse with that ID!").await?;
         return Ok(());
+    };
+
+    let mut roles = parse_role_ids(&autoresponse.allowed_role_ids);
+    if !roles.contains(&role.id) {
+        roles.push(role.id);
+        set_allowed_roles(&conn, autoresponse_id, &roles);
+        reload_triggers(data).await;
     }
 
-    // sock ruse
-    if message.content.to_lowercase().starts_with("sock ruse")  {
-        message.reply_ping(ctx, "it was a DISTACTION").await?;
+    ctx.send(|cr| cr
+        .content(format!("Members with {} can now trigger autoresponse `#{}`!", role.mention(), autoresponse_id))
+        .allowed_mentions(|am| am.empty_parse())
+    ).await?;
+
+    Ok(())
+}
+
+/// Stop members with a role from triggering an autoresponse
+///
+/// Once the last role is removed, everyone can trigger the autoresponse again.
+#[command(slash_command, prefix_command, required_permissions = "MANAGE_MESSAGES", rename = "disallowrole")]
+pub async fn autoresponse_disallow_role(
+    ctx: Context<'_>,
+    #[description = "The autoresponse to change"] autoresponse_id: i32,
+    #[description = "The role which can't trigger it anymore"] role: serenity::Role,
+) -> Result<(), Error> {
+    let data = ctx.data();
+    let conn = data.pool.get().expect("Couldn't get connection from pool");
+    let Some(autoresponse) = get_autoresponse(&conn, autoresponse_id) else {
+        ctx.say("Couldn't find autoresponse with that ID!").await?;
+        return Ok(());
+    };
+
+    let mut roles = parse_role_ids(&autoresponse.allowed_role_ids);
+    if !roles.contains(&role.id) {
+        ctx.say("That role isn't allowed to trigger that autoresponse anyway!").await?;
         return Ok(());
     }
+    roles.retain(|r| *r != role.id);
+    set_allowed_roles(&conn, autoresponse_id, &roles);
+    reload_triggers(data).await;
+
+    let content = if roles.is_empty() {
+        format!("Everyone can trigger autoresponse `#{}` again!", autoresponse_id)
+    } else {
+        format!("Members with {} can't trigger autoresponse `#{}` anymore!", role.mention(), autoresponse_id)
+    };
+    ctx.send(|cr| cr
+        .content(content)
+        .allowed_mentions(|am| am.empty_parse())
+    ).await?;
+
+    Ok(())
+}
+
+async fn set_channel_rule_command(
+    ctx: Context<'_>,
+    channel: serenity::GuildChannel,
+    autoresponse_id: Option<i32>,
+    allow: bool,
+) -> Result<(), Error> {
+    let data = ctx.data();
+    let guild_id = ctx.guild_id().unwrap();
+    {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        if let Some(autoresponse_id) = autoresponse_id {
+            if get_autoresponse(&conn, autoresponse_id).is_none() {
+                ctx.say("Couldn't find autoresponse with that ID!").await?;
+                return Ok(());
+            }
+        }
+
+        set_channel_rule(&conn, &NewAutoresponderChannelRule {
+            guild_id: guild_id.0 as i64,
+            autoresponse_id,
+            channel_id: channel.id.0 as i64,
+            allow,
+        });
+    }
+    reload_triggers(data).await;
+
+    let scope = match autoresponse_id {
+        Some(id) => format!("Autoresponse `#{}`", id),
+        None => "Autoresponses".to_string(),
+    };
+    if allow {
+        ctx.say(format!("{} can now fire in {}! Once there are allowed channels, they only fire in those.", scope, channel.mention())).await?;
+    } else {
+        ctx.say(format!("{} won't fire in {} anymore!", scope, channel.mention())).await?;
+    }
+
+    Ok(())
+}
+
+/// Let autoresponses fire in a channel or category
+///
+/// Once there are allowed channels, autoresponses only fire in those.
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_MESSAGES", rename = "allowchannel")]
+pub async fn autoresponse_allow_channel(
+    ctx: Context<'_>,
+    #[description = "The channel or category"] channel: serenity::GuildChannel,
+    #[description = "Only for this autoresponse (default: all of them)"] autoresponse_id: Option<i32>,
+) -> Result<(), Error> {
+    set_channel_rule_command(ctx, channel, autoresponse_id, true).await
+}
+
+/// Stop autoresponses from firing in a channel or category
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_MESSAGES", rename = "denychannel")]
+pub async fn autoresponse_deny_channel(
+    ctx: Context<'_>,
+    #[description = "The channel or category"] channel: serenity::GuildChannel,
+    #[description = "Only for this autoresponse (default: all of them)"] autoresponse_id: Option<i32>,
+) -> Result<(), Error> {
+    set_channel_rule_command(ctx, channel, autoresponse_id, false).await
+}
+
+/// Remove the allow or deny rule for a channel or category
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_MESSAGES", rename = "clearchannel")]
+pub async fn autoresponse_clear_channel(
+    ctx: Context<'_>,
+    #[description = "The channel or category"] channel: serenity::GuildChannel,
+    #[description = "Only for this autoresponse (default: the rule for all of them)"] autoresponse_id: Option<i32>,
+) -> Result<(), Error> {
+    let data = ctx.data();
+    let guild_id = ctx.guild_id().unwrap();
+    let existed = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        clear_channel_rule(&conn, guild_id.0 as i64, autoresponse_id, channel.id.0 as i64)
+    };
 
-    // Pizza time
-    if message.content.to_lowercase().starts_with("what time is it?") {
-        message.reply_ping(ctx, "https://tenor.com/view/pizza-time-its-delivery-gif-13167414").await?;
+    if existed {
+        reload_triggers(data).await;
+        ctx.say(format!("Removed the rule for {}!", channel.mention())).await?;
+    } else {
+        ctx.say(format!("There was no rule for {}!", channel.mention())).await?;
+    }
+
+    Ok(())
+}
+
+/// List where autoresponses are allowed or denied
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_MESSAGES", rename = "channels")]
+pub async fn autoresponse_channels(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let rules = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        get_guild_channel_rules(&conn, guild_id.0 as i64)
+    };
+
+    if rules.is_empty() {
+        ctx.say("Autoresponses can fire in every channel!").await?;
         return Ok(());
     }
 
-    if message.content.to_lowercase().contains("radio lags") || message.content.to_lowercase().contains("radio is lagging") {
-        let lumi_user = ctx.http.get_user(84774207140945920).await.ok();
-        let mention: String = if let Some(user) = lumi_user {
-            user.mention().to_string()
-        } else {
-            "<@84774207140945920>".to_string()
-        };
+    let lines = rules
+        .iter()
+        .map(|rule| format!(
+            "{} {} {}",
+            match rule.autoresponse_id {
+                Some(id) => format!("`#{}`", id),
+                None => "All autoresponses".to_string(),
+            },
+            if rule.allow { "allowed in" } else { "denied in" },
+            serenity::ChannelId(rule.channel_id as u64).mention()
+        ))
+        .collect::<Vec<_>>();
+    let pages = lines
+        .chunks(AUTORESPONSES_PER_PAGE)
+        .map(|chunk| chunk.join("\n"))
+        .collect::<Vec<_>>();
 
-        message.reply_ping(ctx, mention).await?;
-        message.reply_ping(ctx, "https://media.discordapp.net/attachments/551868267099193374/768520702402887710/unknown.png").await?;
+    paginate(ctx, "Autoresponder channels", &pages, false).await
+}
+
+/// Show which autoresponses are used the most and the least
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_MESSAGES", rename = "stats")]
+pub async fn autoresponse_stats(
+    ctx: Context<'_>,
+    #[description = "How many days to look back (default: 30)"] #[min = 1] #[max = 3650] days: Option<i64>,
+    #[description = "Only count triggers in this channel"] channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let days = days.unwrap_or(30);
+    // prefix commands don't check the limits
+    if !(1..=MAX_STATS_DAYS).contains(&days) {
+        ctx.say(format!("The number of days has to be between 1 and {}.", MAX_STATS_DAYS)).await?;
         return Ok(());
     }
+    let since = Utc::now().naive_utc().date() - chrono::Duration::days(days - 1);
+    let (all, stats) = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        (get_all_autoresponses(&conn), get_stats(&conn, guild_id.0 as i64, since))
+    };
 
-    // Mod only responses
-    let roles = ctx.http.get_guild_roles(message.guild_id.unwrap().0).await?;
-    let mod_role = roles.iter().find(|r| r.name == "Moderator");
-    if let Some(mod_role) = mod_role {
-        let has_role = message.author.has_role(ctx, message.guild_id.unwrap(), mod_role).await?;
-        if has_role {
-            if message.content.starts_with("chirp") {
-                message.reply_ping(ctx, "chirp chirp!").await?;
-                return Ok(());
+    if all.is_empty() {
+        ctx.say("There are no autoresponses yet!").await?;
+        return Ok(());
+    }
+
+    let mut counts: HashMap<i32, i64> = all.iter().map(|a| (a.autoresponse_id, 0)).collect();
+    for stat in &stats {
+        if channel.as_ref().map_or(false, |c| c.id.0 as i64 != stat.channel_id) {
+            continue;
+        }
+        if let Some(count) = counts.get_mut(&stat.autoresponse_id) {
+            *count += stat.count as i64;
+        }
+    }
+
+    let mut ranked: Vec<(&Autoresponse, i64)> = all.iter().map(|a| (a, counts[&a.autoresponse_id])).collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.autoresponse_id.cmp(&b.0.autoresponse_id)));
+    let format_rank = |(autoresponse, count): &(&Autoresponse, i64)| format!(
+        "`#{}` `{}` - {} times",
+        autoresponse.autoresponse_id,
+        autoresponse.pattern.chars().take(40).collect::<String>(),
+        count
+    );
+    let most_used = ranked.iter().take(STATS_LIMIT).map(format_rank).collect::<Vec<_>>().join("\n");
+    let least_used = ranked.iter().rev().take(STATS_LIMIT).map(format_rank).collect::<Vec<_>>().join("\n");
+
+    let scope = match &channel {
+        Some(channel) => format!("in {} ", channel.mention()),
+        None => String::new(),
+    };
+    ctx.send(|cr| cr
+        .embed(|ce| ce
+            .title("Autoresponse statistics")
+            .description(format!("Triggers {}during the last {} days", scope, days))
+            .field("Most used", most_used, false)
+            .field("Least used", least_used, false)
+        )
+    ).await?;
+
+    Ok(())
+}
+
+/// Check which autoresponse a message would trigger, without triggering it
+///
+/// The message is checked as if you sent it in the given channel (default: this one), taking
+/// roles, channel rules and cooldowns into account. Triggers with a chance are reported as such.
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_MESSAGES", rename = "test")]
+pub async fn autoresponse_test(
+    ctx: Context<'_>,
+    #[description = "The message to check"] text: String,
+    #[description = "Check it as if it was sent in this channel (default: this one)"] channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    let data = ctx.data();
+    let guild_id = ctx.guild_id().unwrap();
+    let channel_id = channel.map(|c| c.id).unwrap_or_else(|| ctx.channel_id());
+    let locations = channel_locations(ctx.discord(), channel_id);
+    let roles = guild_id.member(ctx.discord(), ctx.author().id).await?.roles;
+
+    if !channel_permitted(&data.autoresponder_channel_rules.read().await, guild_id.0 as i64, &locations) {
+        ctx.say(format!("Nothing would fire, autoresponses are disabled in {}.", channel_id.mention())).await?;
+        return Ok(());
+    }
+
+    let matching = matching_triggers(data, &text).await;
+    if matching.is_empty() {
+        ctx.say("Nothing would fire, no autoresponse matches that message.").await?;
+        return Ok(());
+    }
+
+    let origin = Origin {
+        guild_id: guild_id.0 as i64,
+        channel_id,
+        user_id: ctx.author().id,
+        locations: &locations,
+        roles: &roles,
+    };
+    let mut lines = Vec::new();
+    let mut result = None;
+    {
+        let cooldowns = data.autoresponse_cooldowns.lock().await;
+        let now = Instant::now();
+        for trigger in matching.iter().take(AUTORESPONSES_PER_PAGE) {
+            let check = check_trigger(trigger, &origin, &cooldowns, now);
+            let chance = trigger.autoresponse.chance_percent;
+            let mut line = format!("{}\n→ {}", describe_autoresponse(&trigger.autoresponse), check.describe());
+            if check == Check::Fires && chance < 100 {
+                line.push_str(&format!(" {}% of the time, otherwise the next match is tried", chance));
             }
+            lines.push(line);
 
-            if message.content.starts_with("bark") {
-                message.reply_ping(ctx, "bork").await?;
-                return Ok(());
+            if check == Check::Fires && chance >= 100 {
+                result = Some(trigger.autoresponse.autoresponse_id);
+                break;
             }
+        }
+    }
+
+    let result = match result {
+        Some(id) => format!("**Result:** `#{}` would fire.", id),
+        None if matching.len() > AUTORESPONSES_PER_PAGE => format!(
+            "**Result:** none of the first {} matches is guaranteed to fire.",
+            AUTORESPONSES_PER_PAGE
+        ),
+        None => "**Result:** nothing is guaranteed to fire.".to_string(),
+    };
+    ctx.send(|cr| cr
+        .content(format!("Matching autoresponses, in the order they are checked:\n{}\n\n{}", lines.join("\n"), result))
+        .allowed_mentions(|am| am.empty_parse())
+    ).await?;
+
+    Ok(())
+}
 
-            if message.content.starts_with("bear") {
-                message.reply_ping(ctx, ":bear:").await?;
+/// The messages a text response is sent as, it's split wherever it contains `{split}`.
+fn response_parts(response: &str) -> Vec<&str> {
+    response
+        .split(RESPONSE_SEPARATOR)
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Respond to a message the way the autoresponse wants to.
+async fn send_response(ctx: &serenity::Context, message: &serenity::Message, kind: ResponseKind, response: String) -> serenity::Result<()> {
+    match kind {
+        ResponseKind::Reply => {
+            for part in response_parts(&response) {
+                message.reply_ping(ctx, part).await?;
+            }
+        },
+        ResponseKind::ReplyWithoutPing => {
+            for part in response_parts(&response) {
+                message.reply(ctx, part).await?;
+            }
+        },
+        ResponseKind::Message => {
+            for part in response_parts(&response) {
+                message.channel_id.say(ctx, part).await?;
+            }
+        },
+        ResponseKind::Reaction => {
+            let Ok(reaction) = serenity::ReactionType::try_from(response.as_str()) else {
+                warn!("Autoresponse reaction `{}` isn't an emoji", response);
+                return Ok(());
+            };
+            message.react(ctx, reaction).await?;
+        },
+        ResponseKind::Sticker => {
+            let Ok(sticker_id) = response.parse::<u64>() else {
+                warn!("Autoresponse sticker `{}` isn't a sticker ID", response);
                 return Ok(());
+            };
+            message.channel_id.send_message(ctx, |m| m.add_sticker_id(serenity::StickerId(sticker_id))).await?;
+        },
+    }
+
+    Ok(())
+}
+
+/// All enabled triggers matching the content, in the order they are checked.
+async fn matching_triggers(data: &Data, content: &str) -> Vec<Trigger> {
+    data.autoresponses.read().await
+        .iter()
+        .filter(|t| t.matches(content))
+        .cloned()
+        .collect()
+}
+
+/// The roles of the author of a guild message, without asking Discord.
+pub fn author_roles(ctx: &serenity::Context, message: &serenity::Message) -> Vec<serenity::RoleId> {
+    if let Some(member) = &message.member {
+        return member.roles.clone();
+    }
+
+    message.guild_id
+        .and_then(|guild_id| ctx.cache.member(guild_id, message.author.id))
+        .map(|member| member.roles)
+        .unwrap_or_default()
+}
+
+pub async fn handle_message(ctx: &serenity::Context, framework: &poise::FrameworkContext<'_, Data, Error>, message: &serenity::Message) -> serenity::Result<()> {
+    if message.author.bot {
+        return Ok(());
+    }
+
+    if message.guild_id.is_none() {
+        return Ok(());
+    }
+
+    let data = framework.user_data().await;
+    let guild_id = message.guild_id.unwrap().0 as i64;
+    let locations = channel_locations(ctx, message.channel_id);
+    if !channel_permitted(&data.autoresponder_channel_rules.read().await, guild_id, &locations) {
+        return Ok(());
+    }
+
+    let matching = matching_triggers(data, &message.content).await;
+    let roles = author_roles(ctx, message);
+    let origin = Origin {
+        guild_id,
+        channel_id: message.channel_id,
+        user_id: message.author.id,
+        locations: &locations,
+        roles: &roles,
+    };
+    for trigger in matching {
+        {
+            let mut cooldowns = data.autoresponse_cooldowns.lock().await;
+            let now = Instant::now();
+            if check_trigger(&trigger, &origin, &cooldowns, now) != Check::Fires {
+                continue;
             }
+            if !roll_chance(trigger.autoresponse.chance_percent) {
+                continue;
+            }
+            cooldowns.start(&trigger.autoresponse, message.channel_id, message.author.id, now);
         }
+
+        let Some(template) = trigger.responses.choose(&mut rand::thread_rng()).cloned() else {
+            continue;
+        };
+        let count = {
+            let conn = data.pool.get().expect("Couldn't get connection from pool");
+            record_stat(&conn, trigger.autoresponse.autoresponse_id, guild_id, message.channel_id.0 as i64);
+            increment_trigger_count(&conn, trigger.autoresponse.autoresponse_id)
+        };
+        let response = render_response(&template, message.author.id, message.channel_id, count);
+
+        send_response(ctx, message, trigger.response_kind, response).await?;
+        return Ok(());
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trigger(pattern: &str, match_kind: MatchKind, case_sensitive: bool) -> Result<Trigger, String> {
+        Trigger::new(Autoresponse {
+            autoresponse_id: 1,
+            pattern: pattern.to_string(),
+            match_kind: match_kind.as_str().to_string(),
+            case_sensitive,
+            moderator_only: false,
+            enabled: true,
+            channel_cooldown_secs: 0,
+            user_cooldown_secs: 0,
+            chance_percent: 100,
+            trigger_count: 0,
+            allowed_role_ids: String::new(),
+            response_kind: ResponseKind::Reply.as_str().to_string(),
+        }, vec!["response".to_string()])
+    }
+
+    #[test]
+    fn test_case_sensitivity() {
+        let sensitive = trigger("test", MatchKind::Exact, true).unwrap();
+        assert!(sensitive.matches("test"));
+        assert!(!sensitive.matches("Test"));
+
+        let insensitive = trigger("crab", MatchKind::Prefix, false).unwrap();
+        assert!(insensitive.matches("CRAB rave"));
+        assert!(!insensitive.matches("a crab"));
+    }
+
+    #[test]
+    fn test_word_match() {
+        let word = trigger("f", MatchKind::Word, false).unwrap();
+        assert!(word.matches("f"));
+        assert!(word.matches("press F to pay respects"));
+        assert!(word.matches("oh no, f."));
+        assert!(!word.matches("fine"));
+        assert!(!word.matches("of"));
+    }
+
+    #[test]
+    fn test_regex_match() {
+        let regex = trigger(r"radio (lags|is lagging)", MatchKind::Regex, false).unwrap();
+        assert!(regex.matches("the Radio lags again"));
+        assert!(regex.matches("radio is lagging"));
+        assert!(!regex.matches("radio is fine"));
+
+        assert!(trigger("(unclosed", MatchKind::Regex, true).is_err());
+    }
+
+    #[test]
+    fn test_render_response() {
+        let user_id = serenity::UserId(84774207140945920);
+        let channel_id = serenity::ChannelId(551868267099193374);
+
+        assert_eq!(
+            render_response("{user} in {channel}, #{count}", user_id, channel_id, 3),
+            "<@84774207140945920> in <#551868267099193374>, #3"
+        );
+        assert_eq!(render_response("hi {mention:1234}", user_id, channel_id, 0), "hi <@1234>");
+        assert_eq!(render_response("{unknown} {mention:abc}", user_id, channel_id, 0), "{unknown} {mention:abc}");
+        assert_eq!(render_response("{user}{split}hi", user_id, channel_id, 0), "<@84774207140945920>{split}hi");
+    }
+
+    #[test]
+    fn test_response_parts() {
+        assert_eq!(response_parts("one message"), vec!["one message"]);
+        assert_eq!(response_parts("first\n{split}\nsecond"), vec!["first", "second"]);
+        assert_eq!(response_parts("{split}only{split} {split}"), vec!["only"]);
+    }
+
+    #[test]
+    fn test_channel_permitted() {
+        let rule = |autoresponse_id, channel_id, allow| AutoresponderChannelRule {
+            rule_id: 0,
+            guild_id: 1,
+            autoresponse_id,
+            channel_id,
+            allow,
+        };
+
+        // no rules, channel 10 in category 100
+        assert!(channel_permitted(&[], 1, &[10, 100]));
+
+        let deny_category = vec![rule(None, 100, false)];
+        assert!(!channel_permitted(&deny_category, 1, &[10, 100]));
+        assert!(channel_permitted(&deny_category, 1, &[20, 200]));
+        assert!(channel_permitted(&deny_category, 2, &[10, 100]));
+
+        let allow_category_deny_channel = vec![rule(Some(1), 100, true), rule(Some(1), 11, false)];
+        assert!(channel_permitted(&allow_category_deny_channel, 1, &[10, 100]));
+        assert!(!channel_permitted(&allow_category_deny_channel, 1, &[11, 100]));
+        assert!(!channel_permitted(&allow_category_deny_channel, 1, &[20, 200]));
+    }
+}
@@ -0,0 +1,560 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use poise::{command, serenity_prelude as serenity};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    add_response, create_autoresponse, delete_autoresponse, get_all_autoresponses, get_all_responses,
+    get_channel_rules, join_role_ids, parse_role_ids, reload_triggers, set_autoresponse_enabled, MatchKind,
+    Trigger,
+};
+use crate::models::{Autoresponse, NewAutoresponderChannelRule, NewAutoresponse};
+use crate::{Context, Error};
+
+const FILE_NAME: &str = "autoresponses.toml";
+const PREVIEW_LENGTH: usize = 1500;
+
+/// The whole trigger list, as it's written to a TOML file.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AutoresponseFile {
+    #[serde(rename = "autoresponse")]
+    autoresponses: Vec<AutoresponseEntry>,
+}
+
+/// A single autoresponse in the file. Everything but how it matches and what it responds with has a
+/// default, so hand written files can stay short.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AutoresponseEntry {
+    match_kind: String,
+    pattern: String,
+    case_sensitive: bool,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    #[serde(default = "default_response_kind")]
+    response_kind: String,
+    responses: Vec<String>,
+    #[serde(default)]
+    channel_cooldown_secs: i32,
+    #[serde(default)]
+    user_cooldown_secs: i32,
+    #[serde(default = "default_chance_percent")]
+    chance_percent: i32,
+    #[serde(default)]
+    allowed_role_ids: Vec<u64>,
+    // has to stay the last field, TOML wants tables after plain values
+    #[serde(default)]
+    channels: Vec<ChannelEntry>,
+}
+
+/// A channel rule of a single autoresponse.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChannelEntry {
+    guild_id: u64,
+    channel_id: u64,
+    allow: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_response_kind() -> String {
+    "reply".to_string()
+}
+
+fn default_chance_percent() -> i32 {
+    100
+}
+
+impl AutoresponseEntry {
+    /// Autoresponses in the file and in the database are matched up by this.
+    fn key(&self) -> (&str, &str) {
+        (&self.match_kind, &self.pattern)
+    }
+
+    fn describe(&self) -> String {
+        let condition = MatchKind::from_name(&self.match_kind)
+            .map(|kind| kind.describe())
+            .unwrap_or("(unknown match kind)");
+
+        format!("message {} `{}`", condition, self.pattern)
+    }
+
+    /// Check whether the autoresponse could be stored and matched, the same way it's checked when
+    /// it's added with a command.
+    fn validate(&self) -> Result<(), String> {
+        if self.responses.is_empty() {
+            return Err("it has no responses".to_string());
+        }
+        if self.channel_cooldown_secs < 0 || self.user_cooldown_secs < 0 {
+            return Err("cooldowns can't be negative".to_string());
+        }
+        if !(1..=100).contains(&self.chance_percent) {
+            return Err("the chance has to be between 1 and 100".to_string());
+        }
+
+        let autoresponse = Autoresponse {
+            autoresponse_id: 0,
+            pattern: self.pattern.clone(),
+            match_kind: self.match_kind.clone(),
+            case_sensitive: self.case_sensitive,
+            moderator_only: false,
+            enabled: self.enabled,
+            channel_cooldown_secs: self.channel_cooldown_secs,
+            user_cooldown_secs: self.user_cooldown_secs,
+            chance_percent: self.chance_percent,
+            trigger_count: 0,
+            allowed_role_ids: String::new(),
+            response_kind: self.response_kind.clone(),
+        };
+        Trigger::new(autoresponse, self.responses.clone()).map(|_| ())
+    }
+
+    /// The names of the fields that differ between two versions of an autoresponse.
+    fn changed_fields(&self, other: &AutoresponseEntry) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.case_sensitive != other.case_sensitive {
+            fields.push("case sensitivity");
+        }
+        if self.enabled != other.enabled {
+            fields.push(if other.enabled { "enabled" } else { "disabled" });
+        }
+        if self.response_kind != other.response_kind {
+            fields.push("response kind");
+        }
+        if self.responses != other.responses {
+            fields.push("responses");
+        }
+        if self.channel_cooldown_secs != other.channel_cooldown_secs || self.user_cooldown_secs != other.user_cooldown_secs {
+            fields.push("cooldowns");
+        }
+        if self.chance_percent != other.chance_percent {
+            fields.push("chance");
+        }
+        if self.allowed_role_ids != other.allowed_role_ids {
+            fields.push("roles");
+        }
+        if self.channels != other.channels {
+            fields.push("channels");
+        }
+
+        fields
+    }
+}
+
+/// What importing a file does to a single autoresponse.
+enum Change {
+    Add(AutoresponseEntry),
+    Update(i32, AutoresponseEntry, AutoresponseEntry),
+    Remove(i32, AutoresponseEntry),
+}
+
+impl Change {
+    fn describe(&self) -> String {
+        match self {
+            Change::Add(entry) => format!("**+** {}", entry.describe()),
+            Change::Update(id, old, new) => format!(
+                "**~** `#{}` {} (changes {})",
+                id,
+                new.describe(),
+                old.changed_fields(new).join(", ")
+            ),
+            Change::Remove(id, old) => format!("**-** `#{}` {}", id, old.describe()),
+        }
+    }
+}
+
+/// All autoresponses in the database, in the order they are checked.
+fn current_entries(conn: &SqliteConnection) -> Vec<(i32, AutoresponseEntry)> {
+    let mut responses: HashMap<i32, Vec<String>> = HashMap::new();
+    for response in get_all_responses(conn) {
+        responses.entry(response.autoresponse_id).or_default().push(response.response);
+    }
+    let mut channels: HashMap<i32, Vec<ChannelEntry>> = HashMap::new();
+    for rule in get_channel_rules(conn) {
+        if let Some(id) = rule.autoresponse_id {
+            channels.entry(id).or_default().push(ChannelEntry {
+                guild_id: rule.guild_id as u64,
+                channel_id: rule.channel_id as u64,
+                allow: rule.allow,
+            });
+        }
+    }
+
+    get_all_autoresponses(conn)
+        .into_iter()
+        .map(|autoresponse| {
+            let id = autoresponse.autoresponse_id;
+            let mut channels = channels.remove(&id).unwrap_or_default();
+            channels.sort();
+            let entry = AutoresponseEntry {
+                allowed_role_ids: parse_role_ids(&autoresponse.allowed_role_ids).iter().map(|r| r.0).collect(),
+                responses: responses.remove(&id).unwrap_or_default(),
+                match_kind: autoresponse.match_kind,
+                pattern: autoresponse.pattern,
+                case_sensitive: autoresponse.case_sensitive,
+                enabled: autoresponse.enabled,
+                response_kind: autoresponse.response_kind,
+                channel_cooldown_secs: autoresponse.channel_cooldown_secs,
+                user_cooldown_secs: autoresponse.user_cooldown_secs,
+                chance_percent: autoresponse.chance_percent,
+                channels,
+            };
+            (id, entry)
+        })
+        .collect()
+}
+
+/// Read the autoresponses from a file, making sure each of them could be imported.
+fn parse_file(content: &str) -> Result<Vec<AutoresponseEntry>, String> {
+    let file: AutoresponseFile = toml::from_str(content).map_err(|e| e.to_string())?;
+
+    let mut seen = HashSet::new();
+    for (i, entry) in file.autoresponses.iter().enumerate() {
+        entry.validate().map_err(|e| format!("autoresponse {} (`{}`): {}", i + 1, entry.pattern, e))?;
+        if !seen.insert(entry.key()) {
+            return Err(format!(
+                "autoresponse {} (`{}`) appears more than once with the match kind `{}`",
+                i + 1,
+                entry.pattern,
+                entry.match_kind
+            ));
+        }
+    }
+
+    Ok(file
+        .autoresponses
+        .into_iter()
+        .map(|mut entry| {
+            entry.channels.sort();
+            entry.channels.dedup();
+            entry
+        })
+        .collect())
+}
+
+/// Work out what has to change so the database holds exactly the wanted autoresponses.
+///
+/// Autoresponses that stay keep their ID, statistics and position in the order they are checked,
+/// new ones are checked after them.
+fn diff(mut current: Vec<(i32, AutoresponseEntry)>, wanted: Vec<AutoresponseEntry>) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for entry in wanted {
+        match current.iter().position(|(_, e)| e.key() == entry.key()) {
+            Some(i) => {
+                let (id, old) = current.remove(i);
+                if old != entry {
+                    changes.push(Change::Update(id, old, entry));
+                }
+            },
+            None => changes.push(Change::Add(entry)),
+        }
+    }
+    changes.extend(current.into_iter().map(|(id, old)| Change::Remove(id, old)));
+
+    changes
+}
+
+fn write_details(conn: &SqliteConnection, of: i32, entry: &AutoresponseEntry) -> QueryResult<()> {
+    use crate::schema::{autoresponder_channel_rules, autoresponse_responses};
+
+    diesel::delete(autoresponse_responses::table.filter(autoresponse_responses::autoresponse_id.eq(of)))
+        .execute(conn)?;
+    for response in &entry.responses {
+        add_response(conn, of, response)?;
+    }
+
+    diesel::delete(autoresponder_channel_rules::table.filter(autoresponder_channel_rules::autoresponse_id.eq(of)))
+        .execute(conn)?;
+    let rules: Vec<NewAutoresponderChannelRule> = entry
+        .channels
+        .iter()
+        .map(|c| NewAutoresponderChannelRule {
+            guild_id: c.guild_id as i64,
+            autoresponse_id: Some(of),
+            channel_id: c.channel_id as i64,
+            allow: c.allow,
+        })
+        .collect();
+    diesel::insert_into(autoresponder_channel_rules::table)
+        .values(&rules)
+        .execute(conn)
+        .map(|_| ())
+}
+
+fn update_from_entry(conn: &SqliteConnection, to_update: i32, entry: &AutoresponseEntry) -> QueryResult<()> {
+    use crate::schema::autoresponses::dsl::*;
+
+    let role_ids: Vec<serenity::RoleId> = entry.allowed_role_ids.iter().map(|r| serenity::RoleId(*r)).collect();
+    diesel::update(autoresponses.filter(autoresponse_id.eq(to_update)))
+        .set((
+            case_sensitive.eq(entry.case_sensitive),
+            enabled.eq(entry.enabled),
+            response_kind.eq(&entry.response_kind),
+            channel_cooldown_secs.eq(entry.channel_cooldown_secs),
+            user_cooldown_secs.eq(entry.user_cooldown_secs),
+            chance_percent.eq(entry.chance_percent),
+            allowed_role_ids.eq(join_role_ids(&role_ids)),
+        ))
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// Apply all changes at once, either all of them are made or none.
+fn apply_changes(conn: &SqliteConnection, changes: &[Change]) -> QueryResult<()> {
+    conn.transaction(|| {
+        for change in changes {
+            match change {
+                Change::Add(entry) => {
+                    let role_ids: Vec<serenity::RoleId> = entry.allowed_role_ids.iter().map(|r| serenity::RoleId(*r)).collect();
+                    let autoresponse = create_autoresponse(conn, &NewAutoresponse {
+                        pattern: &entry.pattern,
+                        match_kind: &entry.match_kind,
+                        case_sensitive: entry.case_sensitive,
+                        channel_cooldown_secs: entry.channel_cooldown_secs,
+                        user_cooldown_secs: entry.user_cooldown_secs,
+                        chance_percent: entry.chance_percent,
+                        allowed_role_ids: &join_role_ids(&role_ids),
+                        response_kind: &entry.response_kind,
+                    })?;
+                    if !entry.enabled {
+                        set_autoresponse_enabled(conn, autoresponse.autoresponse_id, false)?;
+                    }
+                    write_details(conn, autoresponse.autoresponse_id, entry)?;
+                },
+                Change::Update(id, _, entry) => {
+                    update_from_entry(conn, *id, entry)?;
+                    write_details(conn, *id, entry)?;
+                },
+                Change::Remove(id, _) => {
+                    delete_autoresponse(conn, *id)?;
+                },
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// A list of the changes that fits into a message.
+fn describe_changes(changes: &[Change]) -> String {
+    let mut preview = String::new();
+    for (i, change) in changes.iter().enumerate() {
+        let line = change.describe();
+        if preview.len() + line.len() > PREVIEW_LENGTH {
+            preview.push_str(&format!("...and {} more changes", changes.len() - i));
+            break;
+        }
+        preview.push_str(&line);
+        preview.push('\n');
+    }
+
+    preview
+}
+
+/// Add the autoresponses of a TOML file if there are none yet and return how many were added.
+///
+/// This is used to load the file set in `AUTORESPONSES_FILE` on startup. It only sets up the initial
+/// triggers, once there are autoresponses the file is left alone so changes made with commands and
+/// their statistics survive a restart. Use `/autoresponse import` to bring them in line with a file.
+pub fn load_file(conn: &SqliteConnection, path: &str) -> Result<Option<usize>, String> {
+    let current = current_entries(conn);
+    if !current.is_empty() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let wanted = parse_file(&content)?;
+    let changes = diff(current, wanted);
+    apply_changes(conn, &changes).map_err(|e| e.to_string())?;
+
+    Ok(Some(changes.len()))
+}
+
+/// Export all autoresponses as a TOML file
+#[command(slash_command, required_permissions = "MANAGE_MESSAGES", rename = "export")]
+pub async fn autoresponse_export(ctx: Context<'_>) -> Result<(), Error> {
+    let autoresponses: Vec<AutoresponseEntry> = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        current_entries(&conn).into_iter().map(|(_, entry)| entry).collect()
+    };
+    let count = autoresponses.len();
+    let content = toml::to_string_pretty(&AutoresponseFile { autoresponses })?;
+
+    ctx.send(|cr| cr
+        .content(format!("Exported {} autoresponses.", count))
+        .attachment(serenity::AttachmentType::Bytes {
+            data: Cow::Owned(content.into_bytes()),
+            filename: FILE_NAME.to_string(),
+        })
+    ).await?;
+
+    Ok(())
+}
+
+/// Replace the autoresponses with the ones from a TOML file
+///
+/// Autoresponses are matched up by match kind and pattern. You'll see what would change before
+/// anything happens; autoresponses missing from the file are removed along with their statistics.
+#[command(slash_command, required_permissions = "MANAGE_MESSAGES", rename = "import")]
+pub async fn autoresponse_import(
+    ctx: Context<'_>,
+    #[description = "A file created by /autoresponse export"] file: serenity::Attachment,
+) -> Result<(), Error> {
+    let Ok(content) = String::from_utf8(file.download().await?) else {
+        ctx.say("That file isn't a text file.").await?;
+        return Ok(());
+    };
+    let wanted = match parse_file(&content) {
+        Ok(wanted) => wanted,
+        Err(e) => {
+            ctx.send(|cr| cr
+                .content(format!("Couldn't import `{}`: {}", file.filename, e))
+                .allowed_mentions(|am| am.empty_parse())
+            ).await?;
+            return Ok(());
+        },
+    };
+
+    let changes = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        diff(current_entries(&conn), wanted)
+    };
+    if changes.is_empty() {
+        ctx.say("The autoresponses already match that file, there's nothing to import.").await?;
+        return Ok(());
+    }
+
+    let preview = describe_changes(&changes);
+    let ctx_id = ctx.id();
+    let confirm_button_id = format!("{}confirm", ctx_id);
+    let cancel_button_id = format!("{}cancel", ctx_id);
+    let reply = ctx.send(|cr| cr
+        .content(format!("Importing `{}` would make these changes:\n{}", file.filename, preview))
+        .allowed_mentions(|am| am.empty_parse())
+        .components(|cc| cc
+            .create_action_row(|car| car
+                .create_button(|cb| cb
+                    .style(serenity::ButtonStyle::Success)
+                    .label("Import")
+                    .custom_id(&confirm_button_id)
+                )
+                .create_button(|cb| cb
+                    .style(serenity::ButtonStyle::Secondary)
+                    .label("Cancel")
+                    .custom_id(&cancel_button_id)
+                )
+            )
+        )
+    ).await?;
+
+    let press = serenity::CollectComponentInteraction::new(ctx.discord())
+        .author_id(ctx.author().id)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(std::time::Duration::from_secs(60))
+        .collect_limit(1)
+        .await;
+
+    if let Some(press) = &press {
+        press.create_interaction_response(ctx.discord(), |ir| ir
+            .kind(serenity::InteractionResponseType::DeferredUpdateMessage)
+        ).await?;
+    }
+
+    let outcome = match press {
+        None => "The import timed out, nothing was changed.".to_string(),
+        Some(press) if press.data.custom_id != confirm_button_id => "The import was cancelled, nothing was changed.".to_string(),
+        Some(_) => {
+            let result = {
+                let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+                apply_changes(&conn, &changes)
+            };
+            match result {
+                Ok(()) => {
+                    reload_triggers(ctx.data()).await;
+                    format!("Imported `{}`, {} autoresponses changed.", file.filename, changes.len())
+                },
+                Err(e) => format!("The import failed, nothing was changed: {}", e),
+            }
+        },
+    };
+
+    reply.edit(ctx, |m| m
+        .content(format!("{}\n{}", outcome, preview))
+        .allowed_mentions(|am| am.empty_parse())
+        .components(|c| c)
+    ).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let current = parse_file(r#"
+            [[autoresponse]]
+            match_kind = "exact"
+            pattern = "test"
+            case_sensitive = true
+            responses = ["icles"]
+
+            [[autoresponse]]
+            match_kind = "prefix"
+            pattern = "crab"
+            case_sensitive = false
+            responses = ["🦀"]
+        "#).unwrap();
+        let wanted = parse_file(r#"
+            [[autoresponse]]
+            match_kind = "prefix"
+            pattern = "crab"
+            case_sensitive = false
+            responses = ["🦀"]
+
+            [[autoresponse]]
+            match_kind = "exact"
+            pattern = "test"
+            case_sensitive = true
+            responses = ["icles", "ing"]
+
+            [[autoresponse]]
+            match_kind = "word"
+            pattern = "sock"
+            case_sensitive = false
+            responses = ["ruse"]
+        "#).unwrap();
+
+        let changes = diff(current.into_iter().enumerate().map(|(i, e)| (i as i32 + 1, e)).collect(), wanted);
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(&changes[0], Change::Update(1, _, new) if new.responses.len() == 2));
+        assert!(matches!(&changes[1], Change::Add(new) if new.pattern == "sock"));
+    }
+
+    #[test]
+    fn test_parse_file_rejects_duplicates() {
+        let duplicated = r#"
+            [[autoresponse]]
+            match_kind = "exact"
+            pattern = "test"
+            case_sensitive = true
+            responses = ["icles"]
+
+            [[autoresponse]]
+            match_kind = "exact"
+            pattern = "test"
+            case_sensitive = false
+            responses = ["ing"]
+        "#;
+        assert!(parse_file(duplicated).is_err());
+    }
+}
@@ -1,24 +1,460 @@
+use diesel::prelude::*;
+use diesel::SqliteConnection;
 use poise::command;
 use regex::Regex;
 
+use crate::guild_settings::{ensure_guild_settings, get_guild_settings};
+use crate::models::ColorSettingsChanges;
 use crate::{Context, Error};
 
 lazy_static! {
     static ref COLOR_REGEX: Regex = Regex::new(r"^#[a-fA-F0-9]{6}$").unwrap();
 }
 
+/// How many color names are suggested at most, Discord doesn't show more than 25.
+const MAX_SUGGESTIONS: usize = 25;
+/// The chat backgrounds of Discord's themes, names are drawn on these.
+const THEME_BACKGROUNDS: [(&str, u32); 2] = [("dark", 0x313338), ("light", 0xffffff)];
+/// Below this WCAG contrast ratio a name is nearly unreadable on a theme.
+const MIN_CONTRAST: f64 = 2.0;
+
+/// The named colors of CSS.
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+const FORMATS_HELP: &str = "Colors can be written as `#f80`, `#ff8800`, `rgb(255, 136, 0)`, `hsl(32, 100%, 50%)` or a CSS color name like `orange`.";
+
+fn parse_hex(hex: &str) -> Option<u32> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    match hex.len() {
+        // every digit of the short form is doubled, `#f80` is `#ff8800`
+        3 => u32::from_str_radix(hex, 16).ok().map(|short| {
+            let (r, g, b) = ((short >> 8) & 0xf, (short >> 4) & 0xf, short & 0xf);
+            (r * 0x11) << 16 | (g * 0x11) << 8 | b * 0x11
+        }),
+        6 => u32::from_str_radix(hex, 16).ok(),
+        _ => None,
+    }
+}
+
+/// The arguments of a CSS function like `rgb(1, 2, 3)`, separated by commas or spaces.
+fn function_args<'a>(input: &'a str, name: &str) -> Option<Vec<&'a str>> {
+    let args = input.strip_prefix(name)?.trim_start().strip_prefix('(')?.strip_suffix(')')?;
+
+    Some(args.split(|c: char| c == ',' || c.is_whitespace()).filter(|a| !a.is_empty()).collect())
+}
+
+fn parse_rgb(args: &[&str]) -> Result<u32, String> {
+    let error = || "`rgb()` takes three values from 0 to 255, like `rgb(255, 136, 0)`.".to_string();
+    if args.len() != 3 {
+        return Err(error());
+    }
+
+    let mut color = 0;
+    for arg in args {
+        let value: u8 = arg.parse().map_err(|_| error())?;
+        color = color << 8 | value as u32;
+    }
+
+    Ok(color)
+}
+
+fn parse_hsl(args: &[&str]) -> Result<u32, String> {
+    let error = || "`hsl()` takes a hue from 0 to 360 and a saturation and lightness from 0% to 100%, like `hsl(32, 100%, 50%)`.".to_string();
+    let [hue, saturation, lightness] = args else {
+        return Err(error());
+    };
+
+    let hue: f64 = hue.trim_end_matches("deg").parse().map_err(|_| error())?;
+    let percentage = |arg: &str| arg
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .ok()
+        .filter(|p| (0.0..=100.0).contains(p))
+        .map(|p| p / 100.0);
+    let (Some(saturation), Some(lightness)) = (percentage(*saturation), percentage(*lightness)) else {
+        return Err(error());
+    };
+    if !hue.is_finite() {
+        return Err(error());
+    }
+
+    Ok(hsl_to_rgb(hue.rem_euclid(360.0), saturation, lightness))
+}
+
+/// Convert a hue in degrees and a saturation and lightness from 0 to 1 to RGB.
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> u32 {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let channel = |value: f64| ((value + m) * 255.0).round().clamp(0.0, 255.0) as u32;
+
+    channel(r) << 16 | channel(g) << 8 | channel(b)
+}
+
+/// The relative luminance of a color as defined by WCAG, from 0 for black to 1 for white.
+fn relative_luminance(color: u32) -> f64 {
+    let channel = |shift: u32| {
+        let value = ((color >> shift) & 0xff) as f64 / 255.0;
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    0.2126 * channel(16) + 0.7152 * channel(8) + 0.0722 * channel(0)
+}
+
+/// The WCAG contrast ratio of two colors, from 1 for the same color to 21 for black on white.
+fn contrast_ratio(a: u32, b: u32) -> f64 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// The themes a color is nearly unreadable on, with its contrast ratio against them.
+fn unreadable_themes(color: u32) -> Vec<(&'static str, f64)> {
+    THEME_BACKGROUNDS
+        .iter()
+        .map(|(theme, background)| (*theme, contrast_ratio(color, *background)))
+        .filter(|(_, ratio)| *ratio < MIN_CONTRAST)
+        .collect()
+}
+
+/// Mix a color with another one, `amount` is how much of the other one is used, from 0 to 1.
+fn mix(color: u32, other: u32, amount: f64) -> u32 {
+    let channel = |shift: u32| {
+        let (from, to) = (((color >> shift) & 0xff) as f64, ((other >> shift) & 0xff) as f64);
+        (from + (to - from) * amount).round() as u32
+    };
+
+    channel(16) << 16 | channel(8) << 8 | channel(0)
+}
+
+/// The readable color closest to the given one, found by mixing as little black or white into it as possible.
+fn nearest_readable(color: u32) -> u32 {
+    for step in 0..=100 {
+        let amount = step as f64 / 100.0;
+        for other in [0x000000, 0xffffff] {
+            let candidate = mix(color, other, amount);
+            if unreadable_themes(candidate).is_empty() {
+                return candidate;
+            }
+        }
+    }
+
+    // plain grey is readable on every theme, this is only here to be safe
+    0x808080
+}
+
+fn describe_unreadable(color: u32, themes: &[(&str, f64)]) -> String {
+    let themes = themes
+        .iter()
+        .map(|(theme, ratio)| format!("the {} theme (contrast {:.2}:1)", theme, ratio))
+        .collect::<Vec<_>>();
+
+    format!("`#{:06x}` is nearly unreadable on {}", color, themes.join(" and "))
+}
+
+/// Names are compared without case and spaces, so `Light Blue` is `lightblue`.
+fn normalize_name(name: &str) -> String {
+    name.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase()
+}
+
+fn named_color(name: &str) -> Option<u32> {
+    let name = normalize_name(name);
+
+    NAMED_COLORS.iter().find(|(n, _)| *n == name).map(|(_, color)| *color)
+}
+
+/// Color names starting with or containing the given text, the ones starting with it first.
+fn suggest_names(partial: &str) -> Vec<&'static str> {
+    let partial = normalize_name(partial);
+    let starting = NAMED_COLORS.iter().filter(|(n, _)| n.starts_with(&partial));
+    let containing = NAMED_COLORS.iter().filter(|(n, _)| !n.starts_with(&partial) && n.contains(&partial));
+
+    starting.chain(containing).map(|(n, _)| *n).take(MAX_SUGGESTIONS).collect()
+}
+
+/// Parse a color the way CSS writes them, see [`FORMATS_HELP`].
+pub fn parse_color(input: &str) -> Result<u32, String> {
+    let input = input.trim().to_lowercase();
+    if input.is_empty() {
+        return Err(format!("Which color would you like? {}", FORMATS_HELP));
+    }
+
+    if let Some(hex) = input.strip_prefix('#') {
+        return parse_hex(hex).ok_or_else(|| format!(
+            "`{}` isn't a hex color, those have 3 or 6 digits from 0-9 and a-f, like `#f80` or `#ff8800`.",
+            input
+        ));
+    }
+    if let Some(args) = function_args(&input, "rgb") {
+        return parse_rgb(&args);
+    }
+    if let Some(args) = function_args(&input, "hsl") {
+        return parse_hsl(&args);
+    }
+    if let Some(color) = named_color(&input) {
+        return Ok(color);
+    }
+    // the color used to be given as hex without the #
+    if let Some(color) = parse_hex(&input) {
+        return Ok(color);
+    }
+
+    let prefix: String = normalize_name(&input).chars().take(3).collect();
+    let suggestions = suggest_names(&prefix).into_iter().take(3).map(|n| format!("`{}`", n)).collect::<Vec<_>>();
+    if suggestions.is_empty() {
+        Err(format!("I don't know the color `{}`. {}", input, FORMATS_HELP))
+    } else {
+        Err(format!("I don't know the color `{}`, did you mean {}? {}", input, suggestions.join(", "), FORMATS_HELP))
+    }
+}
+
+/// What happens when someone picks a color that is nearly unreadable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum ContrastMode {
+    // Set the color anyway, but suggest a readable one.
+    #[name = "warn"]
+    Warn,
+    // Don't set the color, only suggest a readable one.
+    #[name = "refuse"]
+    Refuse,
+}
+
+impl ContrastMode {
+    /// The name under which this mode is stored in the database.
+    fn as_str(&self) -> &'static str {
+        match self {
+            ContrastMode::Warn => "warn",
+            ContrastMode::Refuse => "refuse",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "warn" => Some(ContrastMode::Warn),
+            "refuse" => Some(ContrastMode::Refuse),
+            _ => None,
+        }
+    }
+}
+
+fn update_color_settings(conn: &SqliteConnection, of_guild: i64, changes: &ColorSettingsChanges) {
+    use crate::schema::guild_settings::dsl::*;
+
+    ensure_guild_settings(conn, of_guild);
+    diesel::update(guild_settings.filter(guild_id.eq(of_guild)))
+        .set(changes)
+        .execute(conn)
+        .expect("Error updating guild settings");
+}
+
+async fn autocomplete_color(_ctx: Context<'_>, partial: &str) -> impl Iterator<Item = String> {
+    suggest_names(partial).into_iter().map(|n| n.to_string()).collect::<Vec<_>>().into_iter()
+}
+
 /// Sets your color
+///
+/// Colors can be written as `#f80`, `#ff8800`, `rgb(255, 136, 0)`, `hsl(32, 100%, 50%)` or a CSS color name.
+/// Colors that are nearly unreadable on Discord's dark or light theme get a warning, or are refused if the server says so.
 #[command(slash_command, prefix_command)]
 pub async fn color(
     ctx: Context<'_>,
-    #[description = "The color to use"] #[rest] color: String,
+    #[description = "The color to use, e.g. #ff8800, rgb(255, 136, 0) or orange"] #[autocomplete = "autocomplete_color"] #[rest] color: String,
 ) -> Result<(), Error> {
-    let color_u64 = if let Some(stripped) = color.strip_prefix('#') {
-        u64::from_str_radix(stripped, 16).unwrap()
-    } else {
-        u64::from_str_radix(&color, 16).unwrap()
+    let color_u64 = match parse_color(&color) {
+        Ok(0) => {
+            ctx.say(format!("Pure black counts as no color on Discord, try `#{:06x}` instead.", nearest_readable(0x000000))).await?;
+            return Ok(());
+        },
+        Ok(parsed) => parsed as u64,
+        Err(e) => {
+            ctx.say(e).await?;
+            return Ok(());
+        },
     };
 
+    let mut warning = None;
+    let unreadable = unreadable_themes(color_u64 as u32);
+    if !unreadable.is_empty() {
+        let mode = {
+            let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+            let settings = get_guild_settings(&conn, ctx.guild_id().unwrap().0 as i64);
+            ContrastMode::from_name(&settings.color_contrast_mode).unwrap_or(ContrastMode::Warn)
+        };
+        let problem = describe_unreadable(color_u64 as u32, &unreadable);
+        let suggestion = nearest_readable(color_u64 as u32);
+
+        if mode == ContrastMode::Refuse {
+            ctx.say(format!("{}, so it can't be used here. The closest readable color is `#{:06x}`.", problem, suggestion)).await?;
+            return Ok(());
+        }
+        warning = Some(format!("{}, maybe try `#{:06x}` instead?", problem, suggestion));
+    }
+
     let author = ctx.author();
     let guild = ctx.guild().unwrap();
     let mut member = guild.member(&ctx.discord(), author.id).await.unwrap();
@@ -56,7 +492,10 @@ pub async fn color(
         member.add_role(&ctx.discord(), created_role.id).await?;
     }
 
-    ctx.say(format!("Set your color to {}!", color)).await?;
+    match warning {
+        Some(warning) => ctx.say(format!("Set your color to #{:06x}! Heads up: {}", color_u64, warning)).await?,
+        None => ctx.say(format!("Set your color to #{:06x}!", color_u64)).await?,
+    };
 
     Ok(())
 }
@@ -77,4 +516,76 @@ pub async fn clearcolor(ctx: Context<'_>) -> Result<(), Error> {
     }
 
     Ok(())
+}
+
+/// Choose whether nearly unreadable colors get a warning or are refused
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn colorcontrast(
+    ctx: Context<'_>,
+    #[description = "What happens when someone picks a nearly unreadable color"] mode: ContrastMode,
+) -> Result<(), Error> {
+    {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        update_color_settings(&conn, ctx.guild_id().unwrap().0 as i64, &ColorSettingsChanges {
+            color_contrast_mode: Some(mode.as_str()),
+        });
+    }
+
+    match mode {
+        ContrastMode::Warn => ctx.say("Nearly unreadable colors will be set with a warning.").await?,
+        ContrastMode::Refuse => ctx.say("Nearly unreadable colors will be refused.").await?,
+    };
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#ff8800"), Ok(0xff8800));
+        assert_eq!(parse_color("#F80"), Ok(0xff8800));
+        assert_eq!(parse_color("ff8800"), Ok(0xff8800));
+        assert_eq!(parse_color("rgb(255, 136, 0)"), Ok(0xff8800));
+        assert_eq!(parse_color("rgb(255 136 0)"), Ok(0xff8800));
+        assert_eq!(parse_color("hsl(0, 100%, 50%)"), Ok(0xff0000));
+        assert_eq!(parse_color("hsl(240deg, 100%, 25%)"), Ok(0x000080));
+        assert_eq!(parse_color("hsl(-120, 100%, 50%)"), Ok(0x0000ff));
+        assert_eq!(parse_color("Rebecca Purple"), Ok(0x663399));
+
+        assert!(parse_color("#ff880").is_err());
+        assert!(parse_color("#gg8800").is_err());
+        assert!(parse_color("rgb(256, 0, 0)").is_err());
+        assert!(parse_color("rgb(1, 2)").is_err());
+        assert!(parse_color("hsl(0, 101%, 50%)").is_err());
+        assert!(parse_color("").is_err());
+        assert!(parse_color("blurple").unwrap_err().contains("`blue`"));
+    }
+
+    #[test]
+    fn test_suggest_names() {
+        let suggestions = suggest_names("green");
+        assert_eq!(suggestions[0], "green");
+        assert!(suggestions.contains(&"darkgreen"));
+        assert_eq!(suggest_names("").len(), MAX_SUGGESTIONS);
+    }
+
+    #[test]
+    fn test_contrast() {
+        assert!((contrast_ratio(0x000000, 0xffffff) - 21.0).abs() < 0.01);
+        assert_eq!(contrast_ratio(0x313338, 0x313338), 1.0);
+
+        assert!(unreadable_themes(0xff0000).is_empty());
+        assert_eq!(unreadable_themes(0x010101)[0].0, "dark");
+        assert_eq!(unreadable_themes(0x0000ff)[0].0, "dark");
+        assert_eq!(unreadable_themes(0xffff00)[0].0, "light");
+
+        for color in [0x000000, 0x010101, 0x0000ff, 0xffff00, 0xfffffe, 0x2f3136] {
+            let readable = nearest_readable(color);
+            assert!(unreadable_themes(readable).is_empty(), "#{:06x} suggested #{:06x}", color, readable);
+        }
+        assert_eq!(nearest_readable(0xff0000), 0xff0000);
+    }
 }
\ No newline at end of file
@@ -358,14 +358,6 @@ pub async fn help(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-/// Post the link to the ban appeal form
-#[command(slash_command, prefix_command, aliases("bananapeel"))]
-pub async fn banappeal(ctx: Context<'_>) -> Result<(), Error> {
-    ctx.say("https://docs.google.com/forms/d/e/1FAIpQLScfna7CI_XMEX-szOBC7h_E1XJDSNCjYEYBId69QwuZnITOCw/viewform").await?;
-
-    Ok(())
-}
-
 /// no
 #[command(slash_command, prefix_command)]
 pub async fn addjohn(ctx: Context<'_>) -> Result<(), Error> {
@@ -1,6 +1,17 @@
 pub mod quotes;
 pub mod autoresponder;
+pub mod automod;
 pub mod cans;
 pub mod misc;
 pub mod colors;
-pub mod suggestions;
\ No newline at end of file
+pub mod suggestions;
+pub mod modlog;
+pub mod warnings;
+pub mod moderation;
+pub mod purge;
+pub mod antispam;
+pub mod raid;
+pub mod notes;
+pub mod modmail;
+pub mod appeals;
+pub mod nicknames;
\ No newline at end of file
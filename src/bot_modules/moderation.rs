@@ -0,0 +1,855 @@
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::SqliteConnection;
+use poise::{command, serenity_prelude::{self as serenity, Mentionable}};
+
+use crate::bot_modules::{appeals, modlog};
+use crate::models::{ModCase, NewModCase};
+use crate::pagination::paginate;
+use crate::{Context, Data, Error};
+
+type Pool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
+
+const CASES_PER_PAGE: usize = 10;
+const CASE_COLOUR: u32 = 0xed4245;
+const LIFTED_COLOUR: u32 = 0x57f287;
+/// How often expired temporary bans are looked for.
+const TEMPBAN_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Discord doesn't allow timeouts longer than 28 days.
+const MAX_TIMEOUT_SECS: i64 = 28 * 24 * 60 * 60;
+/// Anything longer than ten years might as well be permanent.
+const MAX_DURATION_SECS: i64 = 10 * 365 * 24 * 60 * 60;
+/// Discord cuts off audit log reasons after this many characters.
+const AUDIT_LOG_REASON_LENGTH: usize = 512;
+
+/// What a moderator did to a member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseAction {
+    Timeout,
+    Kick,
+    Ban,
+    Tempban,
+    Unban,
+}
+
+impl CaseAction {
+    /// The name under which this action is stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CaseAction::Timeout => "timeout",
+            CaseAction::Kick => "kick",
+            CaseAction::Ban => "ban",
+            CaseAction::Tempban => "tempban",
+            CaseAction::Unban => "unban",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "timeout" => Some(CaseAction::Timeout),
+            "kick" => Some(CaseAction::Kick),
+            "ban" => Some(CaseAction::Ban),
+            "tempban" => Some(CaseAction::Tempban),
+            "unban" => Some(CaseAction::Unban),
+            _ => None,
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            CaseAction::Timeout => "timed out",
+            CaseAction::Kick => "kicked",
+            CaseAction::Ban => "banned",
+            CaseAction::Tempban => "temporarily banned",
+            CaseAction::Unban => "unbanned",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            CaseAction::Timeout => "Timeout",
+            CaseAction::Kick => "Kick",
+            CaseAction::Ban => "Ban",
+            CaseAction::Tempban => "Temporary ban",
+            CaseAction::Unban => "Unban",
+        }
+    }
+}
+
+/// Parse a duration like `2h30m`, `1d` or `90s` into seconds. The units are w, d, h, m and s.
+pub fn parse_duration(input: &str) -> Result<i64, String> {
+    let mut total: i64 = 0;
+    let mut number = String::new();
+    for c in input.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        if c.is_whitespace() && number.is_empty() {
+            continue;
+        }
+
+        let unit = match c.to_ascii_lowercase() {
+            'w' => 7 * 24 * 60 * 60,
+            'd' => 24 * 60 * 60,
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ if number.is_empty() => return Err(format!("`{}` isn't a unit, use w, d, h, m or s", c)),
+            _ => return Err(format!("`{}` needs a unit, e.g. `{}m`", number, number)),
+        };
+        if number.is_empty() {
+            return Err(format!("`{}` needs a number in front of it", c));
+        }
+
+        let value: i64 = number.parse().map_err(|_| "that's way too long".to_string())?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .filter(|total| *total <= MAX_DURATION_SECS)
+            .ok_or_else(|| "that's way too long".to_string())?;
+        number.clear();
+    }
+
+    if !number.is_empty() {
+        return Err(format!("`{}` needs a unit, e.g. `{}m`", number, number));
+    }
+    if total == 0 {
+        return Err("that's not long enough, try something like `2h30m`".to_string());
+    }
+
+    Ok(total)
+}
+
+/// Format seconds the way durations are entered, e.g. `2h 30m`.
+pub fn format_duration(secs: i64) -> String {
+    let units = [("w", 7 * 24 * 60 * 60), ("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60), ("s", 1)];
+
+    let mut rest = secs;
+    let mut parts = Vec::new();
+    for (name, unit) in units {
+        if rest >= unit {
+            parts.push(format!("{}{}", rest / unit, name));
+            rest %= unit;
+        }
+    }
+
+    if parts.is_empty() {
+        "0s".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+/// Whether a member counts as a moderator, going by the permissions their roles give them.
+///
+/// Automatic moderation leaves moderators alone, so they can e.g. post announcements without
+/// tripping anything.
+pub fn is_moderator(ctx: &serenity::Context, guild_id: serenity::GuildId, user_id: serenity::UserId, roles: &[serenity::RoleId]) -> bool {
+    let Some(guild) = ctx.cache.guild(guild_id) else {
+        return false;
+    };
+    if guild.owner_id == user_id {
+        return true;
+    }
+
+    // the @everyone role shares its ID with the guild
+    let permissions = guild.roles
+        .values()
+        .filter(|role| role.id.0 == guild_id.0 || roles.contains(&role.id))
+        .fold(serenity::Permissions::empty(), |permissions, role| permissions | role.permissions);
+
+    permissions.administrator() || permissions.manage_messages() || permissions.moderate_members()
+}
+
+/// The position of the highest of the given roles, 0 for members with only the @everyone role.
+fn highest_role_position(guild: &serenity::Guild, roles: &[serenity::RoleId]) -> i64 {
+    roles
+        .iter()
+        .filter_map(|role_id| guild.roles.get(role_id))
+        .map(|role| role.position)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Make sure the moderator may act on the member and tell them why not otherwise.
+///
+/// Discord only lets moderators act on members below their highest role, the bot has to enforce
+/// the same, or a moderator could use it to act on anyone below the bot's role.
+pub async fn check_hierarchy(ctx: Context<'_>, user: &serenity::User) -> Result<bool, Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let moderator_id = ctx.author().id;
+    let bot_id = ctx.discord().cache.current_user_id();
+
+    let refusal = if user.id == moderator_id {
+        Some("You can't do that to yourself.".to_string())
+    } else if user.id == bot_id {
+        Some("I can't do that to myself.".to_string())
+    } else {
+        // users that aren't members, e.g. when banning someone who left, have no roles in the way
+        match guild_id.member(ctx.discord(), user.id).await {
+            Ok(member) => {
+                let moderator = guild_id.member(ctx.discord(), moderator_id).await?;
+                let bot = guild_id.member(ctx.discord(), bot_id).await?;
+                let Some(guild) = ctx.discord().cache.guild(guild_id) else {
+                    ctx.say("I can't see the roles of this server right now, try again in a bit.").await?;
+                    return Ok(false);
+                };
+
+                let position = highest_role_position(&guild, &member.roles);
+                if user.id == guild.owner_id {
+                    Some(format!("{} owns this server.", user.mention()))
+                } else if moderator_id != guild.owner_id && position >= highest_role_position(&guild, &moderator.roles) {
+                    Some(format!("{} has a role at or above your highest role.", user.mention()))
+                } else if position >= highest_role_position(&guild, &bot.roles) {
+                    Some(format!("{} has a role at or above my highest role.", user.mention()))
+                } else {
+                    None
+                }
+            },
+            Err(_) => None,
+        }
+    };
+
+    let Some(refusal) = refusal else {
+        return Ok(true);
+    };
+    ctx.send(|cr| cr
+        .content(refusal)
+        .allowed_mentions(|am| am.empty_parse())
+    ).await?;
+
+    Ok(false)
+}
+
+fn audit_log_reason(reason: &str) -> String {
+    reason.chars().take(AUDIT_LOG_REASON_LENGTH).collect()
+}
+
+fn create_case(conn: &SqliteConnection, new_case: &NewModCase) -> ModCase {
+    use crate::schema::mod_cases::dsl::*;
+
+    diesel::insert_into(mod_cases)
+        .values(new_case)
+        .execute(conn)
+        .expect("Error creating moderation case");
+
+    mod_cases.order(case_id.desc()).first(conn).unwrap()
+}
+
+fn set_case_log_message(conn: &SqliteConnection, to_update: i32, channel: serenity::ChannelId, message: serenity::MessageId) {
+    use crate::schema::mod_cases::dsl::*;
+
+    diesel::update(mod_cases.filter(case_id.eq(to_update)))
+        .set((log_channel_id.eq(channel.0 as i64), log_message_id.eq(message.0 as i64)))
+        .execute(conn)
+        .expect("Error updating moderation case");
+}
+
+/// Mark the pending temporary bans of a member as lifted, e.g. because they were banned again.
+fn lift_tempbans(conn: &SqliteConnection, of_guild: i64, of_user: i64) {
+    use crate::schema::mod_cases::dsl::*;
+
+    diesel::update(mod_cases
+        .filter(guild_id.eq(of_guild))
+        .filter(user_id.eq(of_user))
+        .filter(action.eq(CaseAction::Tempban.as_str()))
+        .filter(lifted.eq(false)))
+        .set(lifted.eq(true))
+        .execute(conn)
+        .expect("Error updating moderation cases");
+}
+
+fn set_case_lifted(conn: &SqliteConnection, to_update: i32) {
+    use crate::schema::mod_cases::dsl::*;
+
+    diesel::update(mod_cases.filter(case_id.eq(to_update)))
+        .set(lifted.eq(true))
+        .execute(conn)
+        .expect("Error updating moderation case");
+}
+
+fn get_expired_tempbans(conn: &SqliteConnection, now: NaiveDateTime) -> Vec<ModCase> {
+    use crate::schema::mod_cases::dsl::*;
+
+    mod_cases
+        .filter(action.eq(CaseAction::Tempban.as_str()))
+        .filter(lifted.eq(false))
+        .filter(expires_at.le(now))
+        .order(case_id.asc())
+        .load(conn)
+        .expect("Error loading moderation cases")
+}
+
+fn get_case(conn: &SqliteConnection, of_guild: i64, to_get: i32) -> Option<ModCase> {
+    use crate::schema::mod_cases::dsl::*;
+
+    mod_cases
+        .filter(guild_id.eq(of_guild))
+        .filter(case_id.eq(to_get))
+        .first(conn)
+        .ok()
+}
+
+/// All cases of a member, the newest first.
+pub fn get_cases(conn: &SqliteConnection, of_guild: i64, of_user: i64) -> Vec<ModCase> {
+    use crate::schema::mod_cases::dsl::*;
+
+    mod_cases
+        .filter(guild_id.eq(of_guild))
+        .filter(user_id.eq(of_user))
+        .order(case_id.desc())
+        .load(conn)
+        .expect("Error loading moderation cases")
+}
+
+/// The ban a member is serving right now, going by their cases.
+pub fn get_active_ban(conn: &SqliteConnection, of_guild: i64, of_user: i64) -> Option<ModCase> {
+    get_cases(conn, of_guild, of_user)
+        .into_iter()
+        .find(|case| matches!(CaseAction::from_name(&case.action), Some(CaseAction::Ban | CaseAction::Tempban | CaseAction::Unban)))
+        .filter(|case| case.action != CaseAction::Unban.as_str() && !case.lifted)
+}
+
+fn set_case_reason(conn: &SqliteConnection, to_update: i32, new_reason: &str) {
+    use crate::schema::mod_cases::dsl::*;
+
+    diesel::update(mod_cases.filter(case_id.eq(to_update)))
+        .set(reason.eq(new_reason))
+        .execute(conn)
+        .expect("Error updating moderation case");
+}
+
+/// Where a case was posted in the moderation log, if it was.
+pub fn case_log_link(case: &ModCase) -> Option<String> {
+    Some(format!(
+        "https://discord.com/channels/{}/{}/{}",
+        case.guild_id,
+        case.log_channel_id?,
+        case.log_message_id?
+    ))
+}
+
+/// A one line summary of a case.
+pub fn describe_case(case: &ModCase) -> String {
+    let mut line = format!(
+        "`#{}` <t:{}:d> **{}** by {}: {}",
+        case.case_id,
+        case.created_at.timestamp(),
+        CaseAction::from_name(&case.action).map_or(case.action.as_str(), |a| a.title()),
+        serenity::UserId(case.moderator_id as u64).mention(),
+        case.reason
+    );
+    if let Some(link) = case_log_link(case) {
+        line.push_str(&format!(" ([log]({}))", link));
+    }
+
+    line
+}
+
+/// The embed a case is posted to the moderation log with.
+pub fn case_embed(case: &ModCase, user_tag: &str) -> serenity::CreateEmbed {
+    let case_action = CaseAction::from_name(&case.action);
+    let user_id = serenity::UserId(case.user_id as u64);
+
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title(format!("Case #{}: {}", case.case_id, case_action.map_or(case.action.as_str(), |a| a.title())))
+        .colour(if case_action == Some(CaseAction::Unban) { LIFTED_COLOUR } else { CASE_COLOUR })
+        .description(format!(
+            "{} ({}) was {} by {}",
+            user_id.mention(),
+            user_tag,
+            case_action.map_or("punished", |a| a.describe()),
+            serenity::UserId(case.moderator_id as u64).mention()
+        ))
+        .field("Reason", modlog::truncate(&case.reason, 1024), false)
+        .footer(|f| f.text(format!("User ID: {}", user_id)))
+        .timestamp(chrono::DateTime::<Utc>::from_utc(case.created_at, Utc));
+    if let Some(expires_at) = case.expires_at {
+        embed.field("Until", format!("<t:{}:f>", expires_at.timestamp()), true);
+    }
+
+    embed
+}
+
+/// Post a case to the moderation log and remember where, so it can be linked to.
+async fn log_case(ctx: &serenity::Context, pool: &Pool, log_channel_id: Option<serenity::ChannelId>, mut case: ModCase, user_tag: &str) -> ModCase {
+    let guild_id = serenity::GuildId(case.guild_id as u64);
+    let Some(message) = modlog::post_to(ctx, log_channel_id, guild_id, case_embed(&case, user_tag), None).await else {
+        return case;
+    };
+
+    let conn = pool.get().expect("Couldn't get connection from pool");
+    set_case_log_message(&conn, case.case_id, message.channel_id, message.id);
+    case.log_channel_id = Some(message.channel_id.0 as i64);
+    case.log_message_id = Some(message.id.0 as i64);
+
+    case
+}
+
+/// Record something a moderator did to a member as a case and post it to the moderation log.
+pub async fn record_case(ctx: &serenity::Context, data: &Data, user: &serenity::User, new_case: &NewModCase<'_>) -> ModCase {
+    let case = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        if matches!(CaseAction::from_name(new_case.action), Some(CaseAction::Ban | CaseAction::Tempban | CaseAction::Unban)) {
+            // a new ban or an unban replaces whatever temporary ban came before it
+            lift_tempbans(&conn, new_case.guild_id, new_case.user_id);
+        }
+        create_case(&conn, new_case)
+    };
+
+    log_case(ctx, &data.pool, data.log_channel_id, case, &user.tag()).await
+}
+
+/// Time a member out and record it as a case.
+pub async fn apply_timeout(
+    ctx: &serenity::Context,
+    data: &Data,
+    guild_id: serenity::GuildId,
+    user: &serenity::User,
+    moderator_id: serenity::UserId,
+    secs: i64,
+    reason: &str,
+) -> serenity::Result<ModCase> {
+    let until = Utc::now() + chrono::Duration::seconds(secs);
+    guild_id.edit_member(ctx, user.id, |m| m.disable_communication_until_datetime(until.into())).await?;
+
+    Ok(record_case(ctx, data, user, &NewModCase {
+        guild_id: guild_id.0 as i64,
+        user_id: user.id.0 as i64,
+        moderator_id: moderator_id.0 as i64,
+        action: CaseAction::Timeout.as_str(),
+        reason,
+        created_at: Utc::now().naive_utc(),
+        expires_at: Some(until.naive_utc()),
+    }).await)
+}
+
+/// Kick a member and record it as a case.
+pub async fn apply_kick(
+    ctx: &serenity::Context,
+    data: &Data,
+    guild_id: serenity::GuildId,
+    user: &serenity::User,
+    moderator_id: serenity::UserId,
+    reason: &str,
+) -> serenity::Result<ModCase> {
+    guild_id.kick_with_reason(ctx, user.id, &audit_log_reason(reason)).await?;
+
+    Ok(record_case(ctx, data, user, &NewModCase {
+        guild_id: guild_id.0 as i64,
+        user_id: user.id.0 as i64,
+        moderator_id: moderator_id.0 as i64,
+        action: CaseAction::Kick.as_str(),
+        reason,
+        created_at: Utc::now().naive_utc(),
+        expires_at: None,
+    }).await)
+}
+
+/// Unban a user and record it as a case.
+pub async fn apply_unban(
+    ctx: &serenity::Context,
+    data: &Data,
+    guild_id: serenity::GuildId,
+    user: &serenity::User,
+    moderator_id: serenity::UserId,
+    reason: &str,
+) -> serenity::Result<ModCase> {
+    guild_id.unban(ctx, user.id).await?;
+
+    Ok(record_case(ctx, data, user, &NewModCase {
+        guild_id: guild_id.0 as i64,
+        user_id: user.id.0 as i64,
+        moderator_id: moderator_id.0 as i64,
+        action: CaseAction::Unban.as_str(),
+        reason,
+        created_at: Utc::now().naive_utc(),
+        expires_at: None,
+    }).await)
+}
+
+/// Tell a member what's about to happen to them. They might not accept DMs, so this can fail.
+async fn notify(ctx: &serenity::Context, guild_id: serenity::GuildId, user: &serenity::User, action: CaseAction, reason: &str) {
+    let guild_name = guild_id.name(ctx).unwrap_or_else(|| "the server".to_string());
+    let _ = user.direct_message(ctx, |m| m
+        .content(format!("You have been {} in **{}**: {}", action.describe(), guild_name, reason))
+    ).await;
+}
+
+/// Take back a notice sent by [`notify`] when the action didn't go through after all.
+async fn retract_notice(ctx: &serenity::Context, guild_id: serenity::GuildId, user: &serenity::User, action: CaseAction) {
+    let guild_name = guild_id.name(ctx).unwrap_or_else(|| "the server".to_string());
+    let _ = user.direct_message(ctx, |m| m
+        .content(format!("Never mind, you haven't been {} in **{}** after all.", action.describe(), guild_name))
+    ).await;
+}
+
+async fn reply_with_case(ctx: Context<'_>, user: &serenity::User, result: serenity::Result<ModCase>) -> Result<(), Error> {
+    let reply = match result {
+        Ok(case) => {
+            let mut reply = format!(
+                "{} was {} (case `#{}`).",
+                user.mention(),
+                CaseAction::from_name(&case.action).map_or("punished", |a| a.describe()),
+                case.case_id
+            );
+            if let Some(expires_at) = case.expires_at {
+                reply.push_str(&format!(" It ends <t:{}:R>.", expires_at.timestamp()));
+            }
+            reply
+        },
+        Err(e) => format!("That didn't work: {}", e),
+    };
+
+    ctx.send(|cr| cr
+        .content(reply)
+        .allowed_mentions(|am| am.empty_parse())
+    ).await?;
+
+    Ok(())
+}
+
+/// Time a member out
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MODERATE_MEMBERS")]
+pub async fn timeout(
+    ctx: Context<'_>,
+    #[description = "The member to time out"] user: serenity::User,
+    #[description = "How long, e.g. 2h30m (at most 28 days)"] duration: String,
+    #[description = "Why they are timed out"] #[rest] reason: String,
+) -> Result<(), Error> {
+    let secs = match parse_duration(&duration) {
+        Ok(secs) if secs > MAX_TIMEOUT_SECS => {
+            ctx.say("Timeouts can't be longer than 28 days.").await?;
+            return Ok(());
+        },
+        Ok(secs) => secs,
+        Err(e) => {
+            ctx.say(format!("Couldn't read that duration: {}", e)).await?;
+            return Ok(());
+        },
+    };
+
+    if !check_hierarchy(ctx, &user).await? {
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().unwrap();
+    let result = apply_timeout(ctx.discord(), ctx.data(), guild_id, &user, ctx.author().id, secs, &reason).await;
+    if result.is_ok() {
+        let notice = format!("{} ({})", reason, format_duration(secs));
+        notify(ctx.discord(), guild_id, &user, CaseAction::Timeout, &notice).await;
+    }
+
+    reply_with_case(ctx, &user, result).await
+}
+
+/// Kick a member
+#[command(slash_command, prefix_command, guild_only, required_permissions = "KICK_MEMBERS", required_bot_permissions = "KICK_MEMBERS")]
+pub async fn kick(
+    ctx: Context<'_>,
+    #[description = "The member to kick"] user: serenity::User,
+    #[description = "Why they are kicked"] #[rest] reason: String,
+) -> Result<(), Error> {
+    if !check_hierarchy(ctx, &user).await? {
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().unwrap();
+    // once they're kicked, the bot might not share a server with them anymore
+    notify(ctx.discord(), guild_id, &user, CaseAction::Kick, &reason).await;
+    let result = apply_kick(ctx.discord(), ctx.data(), guild_id, &user, ctx.author().id, &reason).await;
+    if result.is_err() {
+        retract_notice(ctx.discord(), guild_id, &user, CaseAction::Kick).await;
+    }
+
+    reply_with_case(ctx, &user, result).await
+}
+
+async fn apply_ban(
+    ctx: Context<'_>,
+    user: &serenity::User,
+    delete_days: u8,
+    reason: &str,
+    secs: Option<i64>,
+) -> serenity::Result<ModCase> {
+    let guild_id = ctx.guild_id().unwrap();
+    let case_action = if secs.is_some() { CaseAction::Tempban } else { CaseAction::Ban };
+    let mut notice = match secs {
+        Some(secs) => format!("{} ({})", reason, format_duration(secs)),
+        None => reason.to_string(),
+    };
+    if appeals::appeal_channel(ctx.discord(), ctx.data(), guild_id).is_some() {
+        notice.push_str("\nIf you'd like to appeal this ban, reply here and explain why you should be unbanned.");
+    }
+    notify(ctx.discord(), guild_id, user, case_action, &notice).await;
+
+    if let Err(e) = guild_id.ban_with_reason(ctx.discord(), user.id, delete_days, audit_log_reason(reason)).await {
+        retract_notice(ctx.discord(), guild_id, user, case_action).await;
+        return Err(e);
+    }
+
+    let now = Utc::now();
+    Ok(record_case(ctx.discord(), ctx.data(), user, &NewModCase {
+        guild_id: guild_id.0 as i64,
+        user_id: user.id.0 as i64,
+        moderator_id: ctx.author().id.0 as i64,
+        action: case_action.as_str(),
+        reason,
+        created_at: now.naive_utc(),
+        expires_at: secs.map(|secs| (now + chrono::Duration::seconds(secs)).naive_utc()),
+    }).await)
+}
+
+/// Ban a member
+#[command(slash_command, prefix_command, guild_only, required_permissions = "BAN_MEMBERS", required_bot_permissions = "BAN_MEMBERS")]
+pub async fn ban(
+    ctx: Context<'_>,
+    #[description = "The member to ban"] user: serenity::User,
+    #[description = "Delete their messages of this many days (default: 0)"] #[min = 0] #[max = 7] delete_days: Option<u8>,
+    #[description = "Why they are banned"] #[rest] reason: String,
+) -> Result<(), Error> {
+    if !check_hierarchy(ctx, &user).await? {
+        return Ok(());
+    }
+
+    let result = apply_ban(ctx, &user, delete_days.unwrap_or(0), &reason, None).await;
+
+    reply_with_case(ctx, &user, result).await
+}
+
+/// Ban a member for some time
+#[command(slash_command, prefix_command, guild_only, required_permissions = "BAN_MEMBERS", required_bot_permissions = "BAN_MEMBERS")]
+pub async fn tempban(
+    ctx: Context<'_>,
+    #[description = "The member to ban"] user: serenity::User,
+    #[description = "How long, e.g. 7d"] duration: String,
+    #[description = "Why they are banned"] #[rest] reason: String,
+) -> Result<(), Error> {
+    let secs = match parse_duration(&duration) {
+        Ok(secs) => secs,
+        Err(e) => {
+            ctx.say(format!("Couldn't read that duration: {}", e)).await?;
+            return Ok(());
+        },
+    };
+
+    if !check_hierarchy(ctx, &user).await? {
+        return Ok(());
+    }
+
+    let result = apply_ban(ctx, &user, 0, &reason, Some(secs)).await;
+
+    reply_with_case(ctx, &user, result).await
+}
+
+async fn fetch_user_tag(ctx: &serenity::Context, user_id: serenity::UserId) -> String {
+    user_id.to_user(ctx).await.map(|u| u.tag()).unwrap_or_else(|_| user_id.to_string())
+}
+
+/// Look up and amend moderation cases
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MODERATE_MEMBERS",
+    subcommands("case_view", "case_reason")
+)]
+pub async fn case(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show a moderation case
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MODERATE_MEMBERS", rename = "view")]
+pub async fn case_view(
+    ctx: Context<'_>,
+    #[description = "The ID of the case"] case_id: i32,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let case = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        get_case(&conn, guild_id.0 as i64, case_id)
+    };
+    let Some(case) = case else {
+        ctx.say("Couldn't find a case with that ID!").await?;
+        return Ok(());
+    };
+
+    let mut embed = case_embed(&case, &fetch_user_tag(ctx.discord(), serenity::UserId(case.user_id as u64)).await);
+    if case.action == CaseAction::Tempban.as_str() {
+        embed.field("Lifted", if case.lifted { "yes" } else { "no" }, true);
+    }
+    embed.field("Log", case_log_link(&case).map_or("not logged".to_string(), |link| format!("[jump]({})", link)), true);
+
+    ctx.send(|cr| cr.embed(|e| {
+        *e = embed;
+        e
+    })).await?;
+
+    Ok(())
+}
+
+/// Change the reason of a moderation case, also in the moderation log
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MODERATE_MEMBERS", rename = "reason")]
+pub async fn case_reason(
+    ctx: Context<'_>,
+    #[description = "The ID of the case"] case_id: i32,
+    #[description = "The new reason"] #[rest] reason: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let case = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        let case = get_case(&conn, guild_id.0 as i64, case_id);
+        if case.is_some() {
+            set_case_reason(&conn, case_id, &reason);
+        }
+        case
+    };
+    let Some(mut case) = case else {
+        ctx.say("Couldn't find a case with that ID!").await?;
+        return Ok(());
+    };
+    case.reason = reason;
+
+    // keep the log in line with the database
+    let mut log_updated = false;
+    if let (Some(channel_id), Some(message_id)) = (case.log_channel_id, case.log_message_id) {
+        let embed = case_embed(&case, &fetch_user_tag(ctx.discord(), serenity::UserId(case.user_id as u64)).await);
+        let result = serenity::ChannelId(channel_id as u64)
+            .edit_message(ctx.discord(), message_id as u64, |m| m.set_embed(embed))
+            .await;
+        match result {
+            Ok(_) => log_updated = true,
+            Err(e) => warn!("Couldn't update the log message of case #{}: {}", case.case_id, e),
+        }
+    }
+
+    ctx.say(if log_updated {
+        format!("Updated the reason of case `#{}`, in the log as well.", case.case_id)
+    } else {
+        format!("Updated the reason of case `#{}`, the log message couldn't be updated.", case.case_id)
+    }).await?;
+
+    Ok(())
+}
+
+/// Show the moderation cases of a member
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MODERATE_MEMBERS")]
+pub async fn cases(
+    ctx: Context<'_>,
+    #[description = "The member whose cases to show"] user: serenity::User,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let all = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        get_cases(&conn, guild_id, user.id.0 as i64)
+    };
+
+    if all.is_empty() {
+        ctx.send(|cr| cr
+            .content(format!("{} has no cases.", user.mention()))
+            .allowed_mentions(|am| am.empty_parse())
+        ).await?;
+        return Ok(());
+    }
+
+    let pages = all
+        .chunks(CASES_PER_PAGE)
+        .map(|chunk| chunk.iter().map(describe_case).collect::<Vec<_>>().join("\n"))
+        .collect::<Vec<_>>();
+
+    paginate(ctx, &format!("Cases of {}", user.tag()), &pages, false).await
+}
+
+/// Lift an expired temporary ban and record that as a case of its own.
+async fn lift_tempban(ctx: &serenity::Context, pool: &Pool, log_channel_id: Option<serenity::ChannelId>, case: ModCase) {
+    let guild_id = serenity::GuildId(case.guild_id as u64);
+    let user_id = serenity::UserId(case.user_id as u64);
+    let result = guild_id.unban(ctx, user_id).await;
+
+    // a failed unban isn't retried, it would most likely fail again every minute
+    let unban = {
+        let conn = pool.get().expect("Couldn't get connection from pool");
+        set_case_lifted(&conn, case.case_id);
+        result.as_ref().ok().map(|_| create_case(&conn, &NewModCase {
+            guild_id: case.guild_id,
+            user_id: case.user_id,
+            moderator_id: ctx.cache.current_user_id().0 as i64,
+            action: CaseAction::Unban.as_str(),
+            reason: &format!("Temporary ban (case #{}) expired", case.case_id),
+            created_at: Utc::now().naive_utc(),
+            expires_at: None,
+        }))
+    };
+
+    let user_tag = fetch_user_tag(ctx, user_id).await;
+    match (unban, result) {
+        (Some(unban), _) => {
+            log_case(ctx, pool, log_channel_id, unban, &user_tag).await;
+        },
+        (None, Err(e)) => {
+            warn!("Couldn't lift temporary ban #{}: {}", case.case_id, e);
+            let mut embed = serenity::CreateEmbed::default();
+            embed
+                .title(format!("Case #{}: Temporary ban expired", case.case_id))
+                .colour(CASE_COLOUR)
+                .description(format!("Couldn't unban {} ({}), please do it by hand: {}", user_id.mention(), user_tag, e))
+                .footer(|f| f.text(format!("User ID: {}", user_id)))
+                .timestamp(Utc::now());
+            modlog::post_to(ctx, log_channel_id, guild_id, embed, None).await;
+        },
+        (None, Ok(())) => (),
+    }
+}
+
+/// Lift temporary bans once they expire. The bans are stored in the database, so ones that expired
+/// while the bot was offline are lifted on the first check after a restart.
+pub fn start_tempban_scheduler(ctx: serenity::Context, pool: Pool, log_channel_id: Option<serenity::ChannelId>) {
+    tokio::spawn(async move {
+        // the first check waits a bit, so the cache knows the log channel by then
+        let start = tokio::time::Instant::now() + TEMPBAN_CHECK_INTERVAL;
+        let mut interval = tokio::time::interval_at(start, TEMPBAN_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let expired = {
+                let conn = pool.get().expect("Couldn't get connection from pool");
+                get_expired_tempbans(&conn, Utc::now().naive_utc())
+            };
+            for case in expired {
+                lift_tempban(&ctx, &pool, log_channel_id, case).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("2h30m"), Ok(2 * 60 * 60 + 30 * 60));
+        assert_eq!(parse_duration("1d 12h"), Ok(36 * 60 * 60));
+        assert_eq!(parse_duration("90s"), Ok(90));
+        assert_eq!(parse_duration("1W"), Ok(7 * 24 * 60 * 60));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("2 30m").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("5y").is_err());
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("99999999999999999999d").is_err());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(2 * 60 * 60 + 30 * 60), "2h 30m");
+        assert_eq!(format_duration(8 * 24 * 60 * 60 + 5), "1w 1d 5s");
+        assert_eq!(format_duration(0), "0s");
+    }
+}
@@ -0,0 +1,398 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use poise::serenity_prelude::{self as serenity, Mentionable};
+
+use crate::Data;
+
+/// How many messages are kept around, so their content is known once they are edited or deleted.
+const MESSAGE_CACHE_SIZE: usize = 10_000;
+/// Embed fields can't be longer than this.
+const FIELD_LENGTH: usize = 1024;
+
+const EDIT_COLOUR: u32 = 0xfaa61a;
+const DELETE_COLOUR: u32 = 0xed4245;
+
+/// What's remembered of a message to log it once it's edited or deleted.
+#[derive(Debug, Clone)]
+pub struct CachedMessage {
+    pub id: serenity::MessageId,
+    pub channel_id: serenity::ChannelId,
+    pub author_id: serenity::UserId,
+    pub author_tag: String,
+    pub content: String,
+    pub attachments: Vec<String>,
+    pub timestamp: serenity::Timestamp,
+}
+
+impl CachedMessage {
+    pub fn from_message(message: &serenity::Message) -> Self {
+        CachedMessage {
+            id: message.id,
+            channel_id: message.channel_id,
+            author_id: message.author.id,
+            author_tag: message.author.tag(),
+            content: message.content.clone(),
+            attachments: message.attachments.iter().map(|a| a.url.clone()).collect(),
+            timestamp: message.timestamp,
+        }
+    }
+}
+
+/// The most recent messages, the oldest ones are forgotten once there are too many.
+///
+/// Serenity drops messages from its own cache before the delete event is handled, which is why
+/// the moderation log keeps its own.
+#[derive(Default)]
+pub struct MessageCache {
+    messages: HashMap<serenity::MessageId, CachedMessage>,
+    order: VecDeque<serenity::MessageId>,
+    skipped: HashSet<serenity::MessageId>,
+    skipped_order: VecDeque<serenity::MessageId>,
+}
+
+impl MessageCache {
+    pub fn insert(&mut self, message: CachedMessage) {
+        if self.order.len() >= MESSAGE_CACHE_SIZE {
+            if let Some(oldest) = self.order.pop_front() {
+                self.messages.remove(&oldest);
+            }
+        }
+
+        self.order.push_back(message.id);
+        self.messages.insert(message.id, message);
+    }
+
+    /// Replace the content of a message and return it as it was before.
+    pub fn update(&mut self, id: serenity::MessageId, content: &str) -> Option<CachedMessage> {
+        let message = self.messages.get_mut(&id)?;
+        let before = message.clone();
+        message.content = content.to_string();
+
+        Some(before)
+    }
+
+    pub fn remove(&mut self, id: serenity::MessageId) -> Option<CachedMessage> {
+        // the ID stays in the order until it's its turn to be dropped, that keeps removing cheap
+        self.messages.remove(&id)
+    }
+
+    /// Don't log the deletion of a message, because whoever deletes it logs it themselves.
+    ///
+    /// The deletion might fail, so skips are forgotten the same way as messages are.
+    pub fn skip_deletion_log(&mut self, id: serenity::MessageId) {
+        if self.skipped_order.len() >= MESSAGE_CACHE_SIZE {
+            if let Some(oldest) = self.skipped_order.pop_front() {
+                self.skipped.remove(&oldest);
+            }
+        }
+
+        self.messages.remove(&id);
+        self.skipped_order.push_back(id);
+        self.skipped.insert(id);
+    }
+
+    /// Whether the deletion of a message shouldn't be logged. Each skip only applies once.
+    fn take_skipped(&mut self, id: serenity::MessageId) -> bool {
+        self.skipped.remove(&id)
+    }
+}
+
+/// The moderation log channel, if there is one and it belongs to the given guild.
+///
+/// Everything that's logged goes through this, so events of other guilds the bot is in never end
+/// up in the log.
+pub fn log_channel(ctx: &serenity::Context, data: &Data, guild_id: serenity::GuildId) -> Option<serenity::ChannelId> {
+    find_log_channel(ctx, data.log_channel_id, guild_id)
+}
+
+fn find_log_channel(ctx: &serenity::Context, log_channel_id: Option<serenity::ChannelId>, guild_id: serenity::GuildId) -> Option<serenity::ChannelId> {
+    let log_channel_id = log_channel_id?;
+    let log_channel = ctx.cache.guild_channel(log_channel_id)?;
+
+    (log_channel.guild_id == guild_id).then_some(log_channel_id)
+}
+
+/// Post an embed to the moderation log of a guild, optionally with a file, and return the message
+/// so it can be linked to.
+///
+/// Failing to log something shouldn't stop whatever is being logged, so errors are only reported.
+pub async fn post(
+    ctx: &serenity::Context,
+    data: &Data,
+    guild_id: serenity::GuildId,
+    embed: serenity::CreateEmbed,
+    file: Option<(String, Vec<u8>)>,
+) -> Option<serenity::Message> {
+    post_to(ctx, data.log_channel_id, guild_id, embed, file).await
+}
+
+/// Like [`post`], for background tasks that only have the configured log channel instead of the
+/// whole user data.
+pub async fn post_to(
+    ctx: &serenity::Context,
+    log_channel_id: Option<serenity::ChannelId>,
+    guild_id: serenity::GuildId,
+    embed: serenity::CreateEmbed,
+    file: Option<(String, Vec<u8>)>,
+) -> Option<serenity::Message> {
+    let log_channel_id = find_log_channel(ctx, log_channel_id, guild_id)?;
+
+    let result = log_channel_id.send_message(ctx, |m| {
+        m.set_embed(embed);
+        if let Some((filename, content)) = file {
+            m.add_file(serenity::AttachmentType::Bytes { data: Cow::Owned(content), filename });
+        }
+        m
+    }).await;
+
+    match result {
+        Ok(message) => Some(message),
+        Err(e) => {
+            error!("Couldn't post to the moderation log: {}", e);
+            None
+        }
+    }
+}
+
+/// Cut a text down to fit into an embed field.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.is_empty() {
+        return "*(no text)*".to_string();
+    }
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+
+    let mut truncated: String = text.chars().take(max - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+fn message_link(guild_id: serenity::GuildId, channel_id: serenity::ChannelId, message_id: serenity::MessageId) -> String {
+    format!("https://discord.com/channels/{}/{}/{}", guild_id, channel_id, message_id)
+}
+
+/// Remember a new message, so it can still be logged after it's gone.
+pub async fn cache_message(ctx: &serenity::Context, data: &Data, message: &serenity::Message) {
+    let Some(guild_id) = message.guild_id else {
+        return;
+    };
+    if message.author.bot || log_channel(ctx, data, guild_id).is_none() {
+        return;
+    }
+
+    data.message_cache.lock().await.insert(CachedMessage::from_message(message));
+}
+
+/// Log the content of a message before and after it was edited.
+pub async fn handle_message_update(ctx: &serenity::Context, data: &Data, event: &serenity::MessageUpdateEvent) {
+    let Some(guild_id) = event.guild_id else {
+        return;
+    };
+    // embeds being added to a message also count as updates, only the content is interesting here
+    let Some(content) = &event.content else {
+        return;
+    };
+    if log_channel(ctx, data, guild_id).is_none() {
+        return;
+    }
+
+    let Some(before) = data.message_cache.lock().await.update(event.id, content) else {
+        return;
+    };
+    if &before.content == content {
+        return;
+    }
+
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title("Message edited")
+        .colour(EDIT_COLOUR)
+        .description(format!(
+            "{} ({}) edited [a message]({}) in {}",
+            before.author_id.mention(),
+            before.author_tag,
+            message_link(guild_id, event.channel_id, event.id),
+            event.channel_id.mention()
+        ))
+        .field("Before", truncate(&before.content, FIELD_LENGTH), false)
+        .field("After", truncate(content, FIELD_LENGTH), false)
+        .footer(|f| f.text(format!("User ID: {} | Message ID: {}", before.author_id, event.id)))
+        .timestamp(chrono::Utc::now());
+
+    post(ctx, data, guild_id, embed, None).await;
+}
+
+/// Log the content of a deleted message, as far as it's known.
+pub async fn handle_message_delete(
+    ctx: &serenity::Context,
+    data: &Data,
+    channel_id: serenity::ChannelId,
+    message_id: serenity::MessageId,
+    guild_id: Option<serenity::GuildId>,
+) {
+    let Some(guild_id) = guild_id else {
+        return;
+    };
+    if log_channel(ctx, data, guild_id).filter(|c| *c != channel_id).is_none() {
+        return;
+    }
+
+    let message = {
+        let mut cache = data.message_cache.lock().await;
+        if cache.take_skipped(message_id) {
+            return;
+        }
+        cache.remove(message_id)
+    };
+
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title("Message deleted")
+        .colour(DELETE_COLOUR)
+        .timestamp(chrono::Utc::now());
+
+    match message {
+        Some(message) => {
+            embed
+                .description(format!(
+                    "A message by {} ({}) was deleted in {}",
+                    message.author_id.mention(),
+                    message.author_tag,
+                    channel_id.mention()
+                ))
+                .field("Content", truncate(&message.content, FIELD_LENGTH), false)
+                .footer(|f| f.text(format!("User ID: {} | Message ID: {}", message.author_id, message_id)));
+            if !message.attachments.is_empty() {
+                embed.field("Attachments", truncate(&message.attachments.join("\n"), FIELD_LENGTH), false);
+            }
+        },
+        None => {
+            embed
+                .description(format!(
+                    "A message was deleted in {}, it's too old to know what it said",
+                    channel_id.mention()
+                ))
+                .footer(|f| f.text(format!("Message ID: {}", message_id)));
+        },
+    }
+
+    post(ctx, data, guild_id, embed, None).await;
+}
+
+/// Log a bulk deletion, with a transcript of the messages that are known.
+pub async fn handle_message_delete_bulk(
+    ctx: &serenity::Context,
+    data: &Data,
+    channel_id: serenity::ChannelId,
+    message_ids: &[serenity::MessageId],
+    guild_id: Option<serenity::GuildId>,
+) {
+    let Some(guild_id) = guild_id else {
+        return;
+    };
+    if log_channel(ctx, data, guild_id).filter(|c| *c != channel_id).is_none() {
+        return;
+    }
+
+    let (deleted, mut messages) = {
+        let mut cache = data.message_cache.lock().await;
+        let deleted: Vec<serenity::MessageId> = message_ids.iter().copied().filter(|id| !cache.take_skipped(*id)).collect();
+        let messages: Vec<CachedMessage> = deleted.iter().filter_map(|id| cache.remove(*id)).collect();
+        (deleted, messages)
+    };
+    if deleted.is_empty() {
+        return;
+    }
+    messages.sort_by_key(|m| m.timestamp.unix_timestamp());
+
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title("Messages deleted")
+        .colour(DELETE_COLOUR)
+        .description(format!(
+            "{} messages were deleted in {}, {} of them are in the transcript",
+            deleted.len(),
+            channel_id.mention(),
+            messages.len()
+        ))
+        .timestamp(chrono::Utc::now());
+
+    let file = (!messages.is_empty()).then(|| {
+        let transcript = messages.iter().map(transcript_line).collect::<Vec<_>>().join("\n");
+        (format!("deleted-{}.txt", channel_id), transcript.into_bytes())
+    });
+
+    post(ctx, data, guild_id, embed, file).await;
+}
+
+/// A message as a line of a plain text transcript.
+pub fn transcript_line(message: &CachedMessage) -> String {
+    let mut line = format!(
+        "[{}] {} ({}): {}",
+        message.timestamp.format("%Y-%m-%d %H:%M:%S"),
+        message.author_tag,
+        message.author_id,
+        message.content
+    );
+    for attachment in &message.attachments {
+        line.push_str(&format!(" [attachment: {}]", attachment));
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: u64, content: &str) -> CachedMessage {
+        CachedMessage {
+            id: serenity::MessageId(id),
+            channel_id: serenity::ChannelId(1),
+            author_id: serenity::UserId(2),
+            author_tag: "someone#0001".to_string(),
+            content: content.to_string(),
+            attachments: Vec::new(),
+            timestamp: serenity::Timestamp::now(),
+        }
+    }
+
+    #[test]
+    fn test_message_cache() {
+        let mut cache = MessageCache::default();
+        for id in 0..MESSAGE_CACHE_SIZE as u64 + 1 {
+            cache.insert(message(id, "hello"));
+        }
+
+        // the oldest message was dropped to make room
+        assert!(cache.remove(serenity::MessageId(0)).is_none());
+
+        let before = cache.update(serenity::MessageId(1), "goodbye").unwrap();
+        assert_eq!(before.content, "hello");
+        assert_eq!(cache.remove(serenity::MessageId(1)).unwrap().content, "goodbye");
+        assert!(cache.remove(serenity::MessageId(1)).is_none());
+    }
+
+    #[test]
+    fn test_skipped_deletions() {
+        let mut cache = MessageCache::default();
+        for id in 0..MESSAGE_CACHE_SIZE as u64 + 1 {
+            cache.skip_deletion_log(serenity::MessageId(id));
+        }
+
+        // a skip whose deletion never came is forgotten eventually
+        assert!(!cache.take_skipped(serenity::MessageId(0)));
+        assert!(cache.take_skipped(serenity::MessageId(1)));
+        assert!(!cache.take_skipped(serenity::MessageId(1)));
+        assert_eq!(cache.skipped.len(), MESSAGE_CACHE_SIZE - 1);
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("", 10), "*(no text)*");
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("ääääääääääää", 5), "ääää…");
+    }
+}
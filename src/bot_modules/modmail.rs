@@ -0,0 +1,468 @@
+use std::borrow::Cow;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use poise::{command, serenity_prelude::{self as serenity, Mentionable}};
+
+use crate::bot_modules::modlog;
+use crate::models::{ModmailMessage, ModmailTicket, NewModmailMessage, NewModmailTicket};
+use crate::{Context, Data, Error, PREFIX};
+
+const MODMAIL_COLOUR: u32 = 0x5865f2;
+const STAFF_COLOUR: u32 = 0x57f287;
+const CLOSED_COLOUR: u32 = 0x99aab5;
+/// Staff messages in a ticket starting with this aren't relayed, so the ticket can be discussed.
+const INTERNAL_PREFIX: &str = "//";
+/// Discord doesn't allow longer thread names.
+const THREAD_NAME_LENGTH: usize = 100;
+/// The JSON error code Discord answers with for channels that don't exist.
+const UNKNOWN_CHANNEL: isize = 10003;
+/// What members are told when their message couldn't be passed on.
+const RELAY_FAILED: &str = "Sorry, your message couldn't be passed on to the moderators. Please try again later.";
+
+fn create_ticket(conn: &SqliteConnection, new_ticket: &NewModmailTicket) -> ModmailTicket {
+    use crate::schema::modmail_tickets::dsl::*;
+
+    diesel::insert_into(modmail_tickets)
+        .values(new_ticket)
+        .execute(conn)
+        .expect("Error creating modmail ticket");
+
+    modmail_tickets.order(ticket_id.desc()).first(conn).unwrap()
+}
+
+fn get_open_ticket_of_user(conn: &SqliteConnection, of_user: i64) -> Option<ModmailTicket> {
+    use crate::schema::modmail_tickets::dsl::*;
+
+    modmail_tickets
+        .filter(user_id.eq(of_user))
+        .filter(closed_at.is_null())
+        .first(conn)
+        .ok()
+}
+
+fn get_ticket_in_thread(conn: &SqliteConnection, of_thread: i64) -> Option<ModmailTicket> {
+    use crate::schema::modmail_tickets::dsl::*;
+
+    modmail_tickets
+        .filter(thread_id.eq(of_thread))
+        .first(conn)
+        .ok()
+}
+
+fn get_ticket(conn: &SqliteConnection, of_guild: i64, to_get: i32) -> Option<ModmailTicket> {
+    use crate::schema::modmail_tickets::dsl::*;
+
+    modmail_tickets
+        .filter(guild_id.eq(of_guild))
+        .filter(ticket_id.eq(to_get))
+        .first(conn)
+        .ok()
+}
+
+fn close_ticket(conn: &SqliteConnection, to_close: i32, by: i64, at: NaiveDateTime) {
+    use crate::schema::modmail_tickets::dsl::*;
+
+    diesel::update(modmail_tickets.filter(ticket_id.eq(to_close)))
+        .set((closed_at.eq(at), closed_by.eq(by)))
+        .execute(conn)
+        .expect("Error closing modmail ticket");
+}
+
+fn add_message(conn: &SqliteConnection, new_message: &NewModmailMessage) {
+    use crate::schema::modmail_messages::dsl::*;
+
+    diesel::insert_into(modmail_messages)
+        .values(new_message)
+        .execute(conn)
+        .expect("Error storing modmail message");
+}
+
+fn get_messages(conn: &SqliteConnection, of_ticket: i32) -> Vec<ModmailMessage> {
+    use crate::schema::modmail_messages::dsl::*;
+
+    modmail_messages
+        .filter(ticket_id.eq(of_ticket))
+        .order(modmail_message_id.asc())
+        .load(conn)
+        .expect("Error loading modmail messages")
+}
+
+/// Store a relayed message, so it ends up in the transcript.
+fn store_message(data: &Data, ticket: &ModmailTicket, message: &serenity::Message, from_staff: bool) {
+    let attachments = message.attachments.iter().map(|a| a.url.clone()).collect::<Vec<_>>().join("\n");
+    let conn = data.pool.get().expect("Couldn't get connection from pool");
+    add_message(&conn, &NewModmailMessage {
+        ticket_id: ticket.ticket_id,
+        author_id: message.author.id.0 as i64,
+        author_tag: &message.author.tag(),
+        from_staff,
+        content: &message.content,
+        attachments: &attachments,
+        created_at: Utc::now().naive_utc(),
+    });
+}
+
+/// A ticket as a plain text transcript.
+fn transcript(ticket: &ModmailTicket, messages: &[ModmailMessage]) -> String {
+    let mut lines = vec![format!(
+        "Modmail ticket #{} with user {}, opened {}",
+        ticket.ticket_id,
+        ticket.user_id,
+        ticket.opened_at.format("%Y-%m-%d %H:%M:%S")
+    )];
+    for message in messages {
+        let mut line = format!(
+            "[{}] {}{} ({}): {}",
+            message.created_at.format("%Y-%m-%d %H:%M:%S"),
+            if message.from_staff { "[staff] " } else { "" },
+            message.author_tag,
+            message.author_id,
+            message.content
+        );
+        for attachment in message.attachments.lines() {
+            line.push_str(&format!(" [attachment: {}]", attachment));
+        }
+        lines.push(line);
+    }
+    if let Some(closed_at) = ticket.closed_at {
+        lines.push(format!("Closed {} by {}", closed_at.format("%Y-%m-%d %H:%M:%S"), ticket.closed_by.unwrap_or_default()));
+    }
+
+    lines.join("\n")
+}
+
+fn transcript_file(ticket: &ModmailTicket, messages: &[ModmailMessage]) -> (String, Vec<u8>) {
+    (format!("modmail-{}.txt", ticket.ticket_id), transcript(ticket, messages).into_bytes())
+}
+
+/// The embed a relayed message is shown with, on either side.
+fn relay_embed(message: &serenity::Message, colour: u32, footer: String) -> serenity::CreateEmbed {
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .author(|a| a.name(message.author.tag()).icon_url(message.author.face()))
+        .colour(colour)
+        .description(modlog::truncate(&message.content, 4096))
+        .footer(|f| f.text(footer))
+        .timestamp(message.timestamp);
+    if !message.attachments.is_empty() {
+        let attachments = message.attachments.iter().map(|a| a.url.clone()).collect::<Vec<_>>().join("\n");
+        embed.field("Attachments", modlog::truncate(&attachments, 1024), false);
+    }
+
+    embed
+}
+
+/// Open a thread for a new ticket in the modmail channel.
+async fn open_ticket(
+    ctx: &serenity::Context,
+    data: &Data,
+    modmail_channel_id: serenity::ChannelId,
+    user: &serenity::User,
+) -> Result<ModmailTicket, Error> {
+    let Some(guild_id) = ctx.cache.guild_channel(modmail_channel_id).map(|c| c.guild_id) else {
+        return Err("The modmail channel isn't a server channel the bot can see".into());
+    };
+
+    let starter = modmail_channel_id.send_message(ctx, |m| m.embed(|e| e
+        .title("New modmail ticket")
+        .colour(MODMAIL_COLOUR)
+        .description(format!(
+            "{} ({}) sent a message. Everything said in the thread is relayed to them, except messages starting with `{}`.",
+            user.mention(),
+            user.tag(),
+            INTERNAL_PREFIX
+        ))
+        .field("Account created", format!("<t:{}:R>", user.created_at().unix_timestamp()), true)
+        .footer(|f| f.text(format!("User ID: {}", user.id)))
+        .timestamp(Utc::now())
+    )).await?;
+    let name: String = format!("modmail {}", user.tag()).chars().take(THREAD_NAME_LENGTH).collect();
+    let thread = modmail_channel_id.create_public_thread(ctx, starter.id, |t| t.name(name)).await?;
+
+    let conn = data.pool.get().expect("Couldn't get connection from pool");
+    Ok(create_ticket(&conn, &NewModmailTicket {
+        guild_id: guild_id.0 as i64,
+        user_id: user.id.0 as i64,
+        thread_id: thread.id.0 as i64,
+        opened_at: Utc::now().naive_utc(),
+    }))
+}
+
+/// Whether Discord said that the channel doesn't exist, e.g. because it was deleted.
+fn is_unknown_channel(error: &serenity::Error) -> bool {
+    match error {
+        serenity::Error::Http(e) => matches!(
+            e.as_ref(),
+            serenity::http::HttpError::UnsuccessfulRequest(response) if response.error.code == UNKNOWN_CHANNEL
+        ),
+        _ => false,
+    }
+}
+
+async fn relay_direct_message(ctx: &serenity::Context, ticket: &ModmailTicket, message: &serenity::Message) -> serenity::Result<()> {
+    let thread_id = serenity::ChannelId(ticket.thread_id as u64);
+    let embed = relay_embed(message, MODMAIL_COLOUR, format!("User ID: {}", message.author.id));
+    thread_id.send_message(ctx, |m| m.set_embed(embed)).await?;
+
+    Ok(())
+}
+
+/// Relay a direct message to the staff, opening a ticket if there's none yet.
+async fn handle_direct_message(ctx: &serenity::Context, data: &Data, modmail_channel_id: serenity::ChannelId, message: &serenity::Message) {
+    let open = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        get_open_ticket_of_user(&conn, message.author.id.0 as i64)
+    };
+    let (ticket, opened) = match open {
+        Some(ticket) => (ticket, false),
+        None => match open_ticket(ctx, data, modmail_channel_id, &message.author).await {
+            Ok(ticket) => (ticket, true),
+            Err(e) => {
+                error!("Couldn't open a modmail ticket: {}", e);
+                let _ = message.channel_id.say(ctx, RELAY_FAILED).await;
+                return;
+            },
+        },
+    };
+
+    let (ticket, opened) = match relay_direct_message(ctx, &ticket, message).await {
+        Ok(()) => (ticket, opened),
+        Err(e) if !opened && is_unknown_channel(&e) => {
+            // the thread was deleted without closing the ticket, so the member gets a new one
+            warn!("The thread of modmail ticket #{} is gone, closing it", ticket.ticket_id);
+            {
+                let conn = data.pool.get().expect("Couldn't get connection from pool");
+                close_ticket(&conn, ticket.ticket_id, ctx.cache.current_user_id().0 as i64, Utc::now().naive_utc());
+            }
+            let reopened: Result<ModmailTicket, Error> = match open_ticket(ctx, data, modmail_channel_id, &message.author).await {
+                Ok(ticket) => relay_direct_message(ctx, &ticket, message).await.map(|()| ticket).map_err(|e| e.into()),
+                Err(e) => Err(e),
+            };
+            match reopened {
+                Ok(ticket) => (ticket, true),
+                Err(e) => {
+                    error!("Couldn't replace modmail ticket #{}: {}", ticket.ticket_id, e);
+                    let _ = message.channel_id.say(ctx, RELAY_FAILED).await;
+                    return;
+                },
+            }
+        },
+        Err(e) => {
+            error!("Couldn't relay a direct message to modmail ticket #{}: {}", ticket.ticket_id, e);
+            let _ = message.channel_id.say(ctx, RELAY_FAILED).await;
+            return;
+        },
+    };
+    store_message(data, &ticket, message, false);
+
+    if opened {
+        let _ = message.channel_id.say(ctx, "Thanks for your message! It was passed on to the moderators, they'll answer you here.").await;
+    } else {
+        let _ = message.react(ctx, '✅').await;
+    }
+}
+
+/// Relay a staff message in a ticket thread to the member.
+async fn handle_staff_message(ctx: &serenity::Context, data: &Data, ticket: ModmailTicket, message: &serenity::Message) {
+    if ticket.closed_at.is_some() {
+        return;
+    }
+    // internal notes and prefix commands aren't meant for the member
+    if message.content.starts_with(INTERNAL_PREFIX) || message.content.starts_with(PREFIX) {
+        return;
+    }
+
+    let guild_name = serenity::GuildId(ticket.guild_id as u64).name(ctx).unwrap_or_else(|| "the server".to_string());
+    let embed = relay_embed(message, STAFF_COLOUR, format!("Moderator of {}", guild_name));
+    let result = match serenity::UserId(ticket.user_id as u64).create_dm_channel(ctx).await {
+        Ok(dm) => dm.send_message(ctx, |m| m.set_embed(embed)).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(()) => {
+            store_message(data, &ticket, message, true);
+            let _ = message.react(ctx, '✅').await;
+        },
+        Err(e) => {
+            warn!("Couldn't relay a staff message of modmail ticket #{}: {}", ticket.ticket_id, e);
+            let _ = message.reply(ctx, format!("This couldn't be delivered, they might not accept DMs: {}", e)).await;
+        },
+    }
+}
+
+/// Relay direct messages to the staff and staff replies back.
+///
+/// Returns whether the message belonged to modmail, in which case nothing else should respond to it.
+pub async fn handle_message(ctx: &serenity::Context, data: &Data, message: &serenity::Message) -> bool {
+    if message.author.bot {
+        return false;
+    }
+    let Some(modmail_channel_id) = data.modmail_channel_id else {
+        return false;
+    };
+
+    if message.guild_id.is_none() {
+        handle_direct_message(ctx, data, modmail_channel_id, message).await;
+        return true;
+    }
+
+    // the cache doesn't keep threads with the other channels, but only ticket threads have a ticket
+    let ticket = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        get_ticket_in_thread(&conn, message.channel_id.0 as i64)
+    };
+    let Some(ticket) = ticket else {
+        return false;
+    };
+    handle_staff_message(ctx, data, ticket, message).await;
+
+    true
+}
+
+/// Manage modmail tickets
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MODERATE_MEMBERS",
+    subcommands("modmail_close", "modmail_transcript")
+)]
+pub async fn modmail(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Close a modmail ticket
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MODERATE_MEMBERS", rename = "close")]
+pub async fn modmail_close(
+    ctx: Context<'_>,
+    #[description = "The ID of the ticket (default: the ticket of this thread)"] ticket_id: Option<i32>,
+    #[description = "Why the ticket is closed, this is sent to the member"] #[rest] reason: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let closed = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        let ticket = match ticket_id {
+            Some(ticket_id) => get_ticket(&conn, guild_id, ticket_id),
+            None => get_ticket_in_thread(&conn, ctx.channel_id().0 as i64),
+        };
+        ticket
+            .filter(|t| t.closed_at.is_none())
+            .map(|ticket| {
+                close_ticket(&conn, ticket.ticket_id, ctx.author().id.0 as i64, Utc::now().naive_utc());
+                let ticket = get_ticket(&conn, ticket.guild_id, ticket.ticket_id).unwrap();
+                let messages = get_messages(&conn, ticket.ticket_id);
+                (ticket, messages)
+            })
+    };
+    let Some((ticket, messages)) = closed else {
+        ctx.say("That isn't an open modmail ticket.").await?;
+        return Ok(());
+    };
+
+    let user_id = serenity::UserId(ticket.user_id as u64);
+    let mut notice = "Your conversation with the moderators was closed.".to_string();
+    if let Some(reason) = &reason {
+        notice.push_str(&format!(" Reason: {}", reason));
+    }
+    notice.push_str(" If you need anything else, just send another message.");
+    if let Ok(dm) = user_id.create_dm_channel(ctx.discord()).await {
+        let _ = dm.say(ctx.discord(), notice).await;
+    }
+
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title(format!("Modmail ticket #{} closed", ticket.ticket_id))
+        .colour(CLOSED_COLOUR)
+        .description(format!(
+            "{} closed the ticket of {} after {} messages",
+            ctx.author().mention(),
+            user_id.mention(),
+            messages.len()
+        ))
+        .footer(|f| f.text(format!("User ID: {}", user_id)))
+        .timestamp(Utc::now());
+    if let Some(reason) = &reason {
+        embed.field("Reason", modlog::truncate(reason, 1024), false);
+    }
+    modlog::post(ctx.discord(), ctx.data(), ctx.guild_id().unwrap(), embed, Some(transcript_file(&ticket, &messages))).await;
+
+    let (filename, content) = transcript_file(&ticket, &messages);
+    ctx.send(|cr| cr
+        .content("The ticket was closed and the member was told. Here's the transcript.")
+        .attachment(serenity::AttachmentType::Bytes { data: Cow::Owned(content), filename })
+    ).await?;
+
+    let thread_id = serenity::ChannelId(ticket.thread_id as u64);
+    if let Err(e) = thread_id.edit_thread(ctx.discord(), |t| t.archived(true).locked(true)).await {
+        warn!("Couldn't archive the thread of modmail ticket #{}: {}", ticket.ticket_id, e);
+    }
+
+    Ok(())
+}
+
+/// Export the transcript of a modmail ticket
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MODERATE_MEMBERS", rename = "transcript")]
+pub async fn modmail_transcript(
+    ctx: Context<'_>,
+    #[description = "The ID of the ticket (default: the ticket of this thread)"] ticket_id: Option<i32>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let found = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        let ticket = match ticket_id {
+            Some(ticket_id) => get_ticket(&conn, guild_id, ticket_id),
+            None => get_ticket_in_thread(&conn, ctx.channel_id().0 as i64),
+        };
+        ticket.map(|ticket| {
+            let messages = get_messages(&conn, ticket.ticket_id);
+            (ticket, messages)
+        })
+    };
+    let Some((ticket, messages)) = found else {
+        ctx.say("Couldn't find that modmail ticket!").await?;
+        return Ok(());
+    };
+
+    let (filename, content) = transcript_file(&ticket, &messages);
+    ctx.send(|cr| cr
+        .content(format!("Transcript of modmail ticket #{} with {}.", ticket.ticket_id, serenity::UserId(ticket.user_id as u64).mention()))
+        .attachment(serenity::AttachmentType::Bytes { data: Cow::Owned(content), filename })
+        .allowed_mentions(|am| am.empty_parse())
+    ).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::connection::SimpleConnection;
+
+    use super::*;
+
+    #[test]
+    fn test_thread_messages_find_their_ticket() {
+        let conn = SqliteConnection::establish(":memory:").unwrap();
+        conn.batch_execute(include_str!("../../migrations/2026-10-19-210000_modmail/up.sql")).unwrap();
+
+        let ticket = create_ticket(&conn, &NewModmailTicket {
+            guild_id: 1,
+            user_id: 2,
+            thread_id: 3,
+            opened_at: Utc::now().naive_utc(),
+        });
+
+        // staff messages are routed by the thread they're sent in, not by the cache
+        let found = get_ticket_in_thread(&conn, 3).expect("the ticket thread has no ticket");
+        assert_eq!(found.ticket_id, ticket.ticket_id);
+        assert!(found.closed_at.is_none());
+        assert!(get_ticket_in_thread(&conn, 4).is_none());
+
+        close_ticket(&conn, ticket.ticket_id, 5, Utc::now().naive_utc());
+        assert!(get_ticket_in_thread(&conn, 3).unwrap().closed_at.is_some());
+        // the member's next message opens a new ticket
+        assert!(get_open_ticket_of_user(&conn, 2).is_none());
+    }
+}
@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use poise::{command, serenity_prelude::{self as serenity, Mentionable}};
+use regex::{Regex, RegexBuilder};
+use rustrict::{Censor, Type};
+
+use crate::bot_modules::automod::Severity;
+use crate::bot_modules::{moderation, modlog};
+use crate::guild_settings::{ensure_guild_settings, get_all_guild_settings, get_guild_settings};
+use crate::models::{GuildSettings, NewNicknamePattern, NicknameFilterSettingsChanges, NicknamePattern};
+use crate::{Context, Data, Error};
+
+const NICKNAME_COLOUR: u32 = 0xe67e22;
+/// Discord doesn't allow longer nicknames.
+const NICKNAME_LENGTH: usize = 32;
+
+/// What happens to an offending nickname.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Action {
+    // Replace the nickname with the configured replacement.
+    #[name = "replace"]
+    Replace,
+    // Remove the nickname, unless the username offends as well.
+    #[name = "reset"]
+    Reset,
+}
+
+impl Action {
+    /// The name under which this action is stored in the database.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Action::Replace => "replace",
+            Action::Reset => "reset",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "replace" => Some(Action::Replace),
+            "reset" => Some(Action::Reset),
+            _ => None,
+        }
+    }
+}
+
+/// The nickname filter settings of a guild, ready to be checked against names.
+#[derive(Clone)]
+pub struct NicknameFilterConfig {
+    pub threshold: Option<Type>,
+    pub patterns: Vec<Regex>,
+    pub action: Action,
+    pub replacement: String,
+}
+
+impl NicknameFilterConfig {
+    fn new(settings: &GuildSettings, patterns: &[NicknamePattern]) -> Self {
+        let severity = Severity::from_name(&settings.nickname_filter_severity).unwrap_or(Severity::Off);
+
+        NicknameFilterConfig {
+            threshold: severity.rustrict_type().map(|severity| Type::INAPPROPRIATE & severity),
+            // patterns are validated when they're added
+            patterns: patterns.iter().filter_map(|p| build_pattern(&p.pattern).ok()).collect(),
+            action: Action::from_name(&settings.nickname_filter_action).unwrap_or(Action::Replace),
+            replacement: settings.nickname_replacement.clone(),
+        }
+    }
+
+    /// Whether a name crosses the threshold or matches a pattern.
+    pub fn offends(&self, name: &str) -> bool {
+        if self.patterns.iter().any(|p| p.is_match(name)) {
+            return true;
+        }
+
+        self.threshold.map_or(false, |threshold| Censor::from_str(name).analyze().is(threshold))
+    }
+
+    /// The nickname a member should get instead of the one they have, if it needs to change.
+    ///
+    /// `Some(None)` means the nickname should be removed.
+    fn correct(&self, nickname: Option<&str>, username: &str) -> Option<Option<String>> {
+        match nickname {
+            Some(nickname) if self.offends(nickname) => (),
+            // a nickname that's fine hides the username
+            Some(_) => return None,
+            None if self.offends(username) => (),
+            None => return None,
+        }
+
+        if self.action == Action::Reset && !self.offends(username) {
+            Some(None)
+        } else {
+            Some(Some(self.replacement.clone()))
+        }
+    }
+}
+
+fn build_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).size_limit(1 << 16).build()
+}
+
+fn get_patterns(conn: &SqliteConnection) -> Vec<NicknamePattern> {
+    use crate::schema::nickname_patterns::dsl::*;
+
+    nickname_patterns
+        .order(pattern_id.asc())
+        .load(conn)
+        .expect("Error loading nickname patterns")
+}
+
+fn get_guild_patterns(conn: &SqliteConnection, of_guild: i64) -> Vec<NicknamePattern> {
+    use crate::schema::nickname_patterns::dsl::*;
+
+    nickname_patterns
+        .filter(guild_id.eq(of_guild))
+        .order(pattern_id.asc())
+        .load(conn)
+        .expect("Error loading nickname patterns")
+}
+
+fn add_pattern(conn: &SqliteConnection, new_pattern: &NewNicknamePattern) {
+    use crate::schema::nickname_patterns;
+
+    diesel::insert_into(nickname_patterns::table)
+        .values(new_pattern)
+        .execute(conn)
+        .expect("Error adding nickname pattern");
+}
+
+fn remove_pattern(conn: &SqliteConnection, of_guild: i64, to_remove: i32) -> bool {
+    use crate::schema::nickname_patterns::dsl::*;
+
+    let deleted = diesel::delete(nickname_patterns
+        .filter(guild_id.eq(of_guild))
+        .filter(pattern_id.eq(to_remove)))
+        .execute(conn)
+        .expect("Error deleting nickname pattern");
+
+    deleted > 0
+}
+
+fn update_nickname_filter_settings(conn: &SqliteConnection, of_guild: i64, changes: &NicknameFilterSettingsChanges) {
+    use crate::schema::guild_settings::dsl::*;
+
+    ensure_guild_settings(conn, of_guild);
+    diesel::update(guild_settings.filter(guild_id.eq(of_guild)))
+        .set(changes)
+        .execute(conn)
+        .expect("Error updating guild settings");
+}
+
+/// Load the nickname filter settings of all guilds that have it enabled.
+pub fn load_nickname_filter_configs(conn: &SqliteConnection) -> HashMap<serenity::GuildId, NicknameFilterConfig> {
+    let mut patterns: HashMap<i64, Vec<NicknamePattern>> = HashMap::new();
+    for pattern in get_patterns(conn) {
+        patterns.entry(pattern.guild_id).or_default().push(pattern);
+    }
+
+    get_all_guild_settings(conn)
+        .into_iter()
+        .filter(|settings| settings.nickname_filter_enabled)
+        .map(|settings| {
+            let guild_patterns = patterns.remove(&settings.guild_id).unwrap_or_default();
+            (serenity::GuildId(settings.guild_id as u64), NicknameFilterConfig::new(&settings, &guild_patterns))
+        })
+        .collect()
+}
+
+/// Reload the nickname filter cache, so changes to the settings take effect immediately.
+pub async fn reload_nickname_filter_configs(data: &Data) {
+    let configs = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        load_nickname_filter_configs(&conn)
+    };
+    *data.nickname_filter.write().await = configs;
+}
+
+/// Check the name of a member that joined or changed and correct it if it offends.
+pub async fn check_member(ctx: &serenity::Context, data: &Data, member: &serenity::Member) {
+    let Some(config) = data.nickname_filter.read().await.get(&member.guild_id).cloned() else {
+        return;
+    };
+    if member.user.bot || moderation::is_moderator(ctx, member.guild_id, member.user.id, &member.roles) {
+        return;
+    }
+    let Some(corrected) = config.correct(member.nick.as_deref(), &member.user.name) else {
+        return;
+    };
+
+    let result = member.guild_id.edit_member(ctx, member.user.id, |m| m.nickname(corrected.as_deref().unwrap_or(""))).await;
+    let before = member.nick.clone().unwrap_or_else(|| format!("{} (username)", member.user.name));
+    let after = corrected.unwrap_or_else(|| format!("{} (username)", member.user.name));
+    let outcome = match result {
+        Ok(_) => format!("was changed to **{}**", after),
+        Err(e) => {
+            warn!("Couldn't moderate the nickname of {}: {}", member.user.tag(), e);
+            format!("couldn't be changed: {}", e)
+        },
+    };
+
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title("Nickname moderated")
+        .colour(NICKNAME_COLOUR)
+        .description(format!("The name of {} ({}) {}", member.user.mention(), member.user.tag(), outcome))
+        .field("Before", modlog::truncate(&before, 1024), false)
+        .footer(|f| f.text(format!("User ID: {}", member.user.id)))
+        .timestamp(chrono::Utc::now());
+    modlog::post(ctx, data, member.guild_id, embed, None).await;
+}
+
+/// Check a member's name again if it changed.
+pub async fn handle_member_update(ctx: &serenity::Context, data: &Data, old: Option<&serenity::Member>, new: &serenity::Member) {
+    // role changes and the like don't need another check
+    if old.map_or(false, |old| old.nick == new.nick && old.user.name == new.user.name) {
+        return;
+    }
+
+    check_member(ctx, data, new).await;
+}
+
+/// Configure the filtering of nicknames and usernames
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    rename = "nicknamefilter",
+    subcommands(
+        "nickname_filter_settings",
+        "nickname_filter_toggle",
+        "nickname_filter_severity",
+        "nickname_filter_action",
+        "nickname_filter_add_pattern",
+        "nickname_filter_remove_pattern"
+    )
+)]
+pub async fn nickname_filter(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show the nickname filter settings of this server
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "settings")]
+pub async fn nickname_filter_settings(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let (settings, patterns) = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        (get_guild_settings(&conn, guild_id), get_guild_patterns(&conn, guild_id))
+    };
+
+    let patterns = patterns
+        .iter()
+        .map(|p| format!("`#{}` `{}`", p.pattern_id, p.pattern))
+        .collect::<Vec<_>>();
+    let action = match Action::from_name(&settings.nickname_filter_action) {
+        Some(Action::Reset) => "reset".to_string(),
+        _ => format!("replace with \"{}\"", settings.nickname_replacement),
+    };
+
+    ctx.say(format!(
+        "The nickname filter is **{}**.\nThreshold: {}\nAction: {}\nPatterns: {}\nModerators are always exempt.",
+        if settings.nickname_filter_enabled { "enabled" } else { "disabled" },
+        settings.nickname_filter_severity,
+        action,
+        if patterns.is_empty() { "none".to_string() } else { patterns.join(", ") }
+    )).await?;
+
+    Ok(())
+}
+
+/// Turn the nickname filter on or off
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "toggle")]
+pub async fn nickname_filter_toggle(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let enabled = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        let enabled = !get_guild_settings(&conn, guild_id).nickname_filter_enabled;
+        update_nickname_filter_settings(&conn, guild_id, &NicknameFilterSettingsChanges {
+            nickname_filter_enabled: Some(enabled),
+            ..Default::default()
+        });
+        enabled
+    };
+    reload_nickname_filter_configs(ctx.data()).await;
+
+    ctx.say(format!("The nickname filter is now {}.", if enabled { "enabled" } else { "disabled" })).await?;
+
+    Ok(())
+}
+
+/// Set how bad a name has to be before it's filtered
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "severity")]
+pub async fn nickname_filter_severity(
+    ctx: Context<'_>,
+    #[description = "The lowest severity that is filtered"] severity: Severity,
+) -> Result<(), Error> {
+    {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        update_nickname_filter_settings(&conn, ctx.guild_id().unwrap().0 as i64, &NicknameFilterSettingsChanges {
+            nickname_filter_severity: Some(severity.as_str()),
+            ..Default::default()
+        });
+    }
+    reload_nickname_filter_configs(ctx.data()).await;
+
+    ctx.say(format!("The nickname filter threshold is now {}.", severity.as_str())).await?;
+
+    Ok(())
+}
+
+/// Set what happens to offending names
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "action")]
+pub async fn nickname_filter_action(
+    ctx: Context<'_>,
+    #[description = "What to do with offending names"] action: Action,
+    #[description = "The nickname offending names are replaced with"] #[rest] replacement: Option<String>,
+) -> Result<(), Error> {
+    let replacement = replacement.map(|r| r.trim().to_string());
+    if replacement.as_ref().map_or(false, |r| r.is_empty() || r.chars().count() > NICKNAME_LENGTH) {
+        ctx.say(format!("The replacement has to be between 1 and {} characters long.", NICKNAME_LENGTH)).await?;
+        return Ok(());
+    }
+
+    {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        update_nickname_filter_settings(&conn, ctx.guild_id().unwrap().0 as i64, &NicknameFilterSettingsChanges {
+            nickname_filter_action: Some(action.as_str()),
+            nickname_replacement: replacement.as_deref(),
+            ..Default::default()
+        });
+    }
+    reload_nickname_filter_configs(ctx.data()).await;
+
+    ctx.say(format!("Offending names are now {}.", match action {
+        Action::Replace => "replaced",
+        Action::Reset => "reset",
+    })).await?;
+
+    Ok(())
+}
+
+/// Filter names matching a regex
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "addpattern")]
+pub async fn nickname_filter_add_pattern(
+    ctx: Context<'_>,
+    #[description = "A case insensitive regex"] #[rest] pattern: String,
+) -> Result<(), Error> {
+    if let Err(e) = build_pattern(&pattern) {
+        ctx.say(format!("That regex doesn't work: {}", e)).await?;
+        return Ok(());
+    }
+
+    {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        add_pattern(&conn, &NewNicknamePattern {
+            guild_id: ctx.guild_id().unwrap().0 as i64,
+            pattern: &pattern,
+        });
+    }
+    reload_nickname_filter_configs(ctx.data()).await;
+
+    ctx.say(format!("Names matching `{}` are now filtered.", pattern)).await?;
+
+    Ok(())
+}
+
+/// Stop filtering names matching a regex
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "removepattern")]
+pub async fn nickname_filter_remove_pattern(
+    ctx: Context<'_>,
+    #[description = "The ID of the pattern, see the settings"] pattern_id: i32,
+) -> Result<(), Error> {
+    let removed = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        remove_pattern(&conn, ctx.guild_id().unwrap().0 as i64, pattern_id)
+    };
+    reload_nickname_filter_configs(ctx.data()).await;
+
+    if removed {
+        ctx.say(format!("Removed pattern `#{}`.", pattern_id)).await?;
+    } else {
+        ctx.say("Couldn't find a pattern with that ID!").await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(action: Action) -> NicknameFilterConfig {
+        NicknameFilterConfig {
+            threshold: None,
+            patterns: vec![build_pattern("discord\\.gg").unwrap()],
+            action,
+            replacement: "Moderated Nickname".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_correct() {
+        let replace = config(Action::Replace);
+        assert_eq!(replace.correct(Some("Join DISCORD.GG/spam"), "someone"), Some(Some("Moderated Nickname".to_string())));
+        assert_eq!(replace.correct(Some("someone"), "discord.gg/spam"), None);
+        assert_eq!(replace.correct(None, "discord.gg/spam"), Some(Some("Moderated Nickname".to_string())));
+        assert_eq!(replace.correct(None, "someone"), None);
+
+        let reset = config(Action::Reset);
+        assert_eq!(reset.correct(Some("discord.gg/spam"), "someone"), Some(None));
+        // resetting would only bring the offending username back
+        assert_eq!(reset.correct(Some("discord.gg/spam"), "discord.gg/more"), Some(Some("Moderated Nickname".to_string())));
+    }
+}
@@ -0,0 +1,233 @@
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use poise::{command, serenity_prelude::{self as serenity, Mentionable}};
+
+use crate::bot_modules::{moderation, modlog, warnings};
+use crate::models::{NewNote, Note};
+use crate::pagination::paginate;
+use crate::{Context, Error};
+
+const NOTES_PER_PAGE: usize = 10;
+/// How many warnings, cases and notes `/userinfo` shows, the full lists have their own commands.
+const USERINFO_RECENT: usize = 5;
+const USERINFO_COLOUR: u32 = 0x5865f2;
+
+fn create_note(conn: &SqliteConnection, new_note: &NewNote) -> Note {
+    use crate::schema::notes::dsl::*;
+
+    diesel::insert_into(notes)
+        .values(new_note)
+        .execute(conn)
+        .expect("Error creating note");
+
+    notes.order(note_id.desc()).first(conn).unwrap()
+}
+
+/// All notes on a member, the newest first.
+fn get_notes(conn: &SqliteConnection, of_guild: i64, of_user: i64) -> Vec<Note> {
+    use crate::schema::notes::dsl::*;
+
+    notes
+        .filter(guild_id.eq(of_guild))
+        .filter(user_id.eq(of_user))
+        .order(note_id.desc())
+        .load(conn)
+        .expect("Error loading notes")
+}
+
+fn delete_note(conn: &SqliteConnection, of_guild: i64, to_delete: i32) -> bool {
+    use crate::schema::notes::dsl::*;
+
+    let deleted = diesel::delete(notes
+        .filter(guild_id.eq(of_guild))
+        .filter(note_id.eq(to_delete)))
+        .execute(conn)
+        .expect("Error deleting note");
+
+    deleted > 0
+}
+
+fn describe_note(note: &Note) -> String {
+    format!(
+        "`#{}` <t:{}:d> by {}: {}",
+        note.note_id,
+        note.created_at.timestamp(),
+        serenity::UserId(note.moderator_id as u64).mention(),
+        note.content
+    )
+}
+
+/// A timestamp both as a date and how long ago it was.
+fn describe_timestamp(timestamp: serenity::Timestamp) -> String {
+    format!("<t:{0}:D> (<t:{0}:R>)", timestamp.unix_timestamp())
+}
+
+/// The newest entries of a list for a `/userinfo` field.
+fn recent_field<T>(entries: &[T], describe: fn(&T) -> String) -> String {
+    if entries.is_empty() {
+        return "none".to_string();
+    }
+
+    let mut lines = entries.iter().take(USERINFO_RECENT).map(describe).collect::<Vec<_>>();
+    if entries.len() > USERINFO_RECENT {
+        lines.push(format!("…and {} older", entries.len() - USERINFO_RECENT));
+    }
+    modlog::truncate(&lines.join("\n"), 1024)
+}
+
+/// Keep private notes on members, only moderators can see them
+///
+/// These are slash commands only, so the replies can be hidden from everyone else.
+#[command(
+    slash_command,
+    guild_only,
+    required_permissions = "MODERATE_MEMBERS",
+    subcommands("note_add", "note_list", "note_remove")
+)]
+pub async fn note(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Add a note on a member
+#[command(slash_command, guild_only, required_permissions = "MODERATE_MEMBERS", rename = "add")]
+pub async fn note_add(
+    ctx: Context<'_>,
+    #[description = "The member the note is about"] user: serenity::User,
+    #[description = "The note"] #[rest] text: String,
+) -> Result<(), Error> {
+    let note = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        create_note(&conn, &NewNote {
+            guild_id: ctx.guild_id().unwrap().0 as i64,
+            user_id: user.id.0 as i64,
+            moderator_id: ctx.author().id.0 as i64,
+            content: &text,
+            created_at: Utc::now().naive_utc(),
+        })
+    };
+
+    ctx.send(|cr| cr
+        .content(format!("Added note `#{}` on {}.", note.note_id, user.mention()))
+        .allowed_mentions(|am| am.empty_parse())
+        .ephemeral(true)
+    ).await?;
+
+    Ok(())
+}
+
+/// Show the notes on a member
+#[command(slash_command, guild_only, required_permissions = "MODERATE_MEMBERS", rename = "list")]
+pub async fn note_list(
+    ctx: Context<'_>,
+    #[description = "The member whose notes to show"] user: serenity::User,
+) -> Result<(), Error> {
+    let all = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        get_notes(&conn, ctx.guild_id().unwrap().0 as i64, user.id.0 as i64)
+    };
+
+    if all.is_empty() {
+        ctx.send(|cr| cr
+            .content(format!("There are no notes on {}.", user.mention()))
+            .allowed_mentions(|am| am.empty_parse())
+            .ephemeral(true)
+        ).await?;
+        return Ok(());
+    }
+
+    let pages = all
+        .chunks(NOTES_PER_PAGE)
+        .map(|chunk| chunk.iter().map(describe_note).collect::<Vec<_>>().join("\n"))
+        .collect::<Vec<_>>();
+
+    paginate(ctx, &format!("Notes on {}", user.tag()), &pages, true).await
+}
+
+/// Remove a note
+#[command(slash_command, guild_only, required_permissions = "MODERATE_MEMBERS", rename = "remove")]
+pub async fn note_remove(
+    ctx: Context<'_>,
+    #[description = "The ID of the note"] note_id: i32,
+) -> Result<(), Error> {
+    let deleted = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        delete_note(&conn, ctx.guild_id().unwrap().0 as i64, note_id)
+    };
+
+    let reply = if deleted {
+        format!("Removed note `#{}`.", note_id)
+    } else {
+        "Couldn't find a note with that ID!".to_string()
+    };
+    ctx.send(|cr| cr
+        .content(reply)
+        .ephemeral(true)
+    ).await?;
+
+    Ok(())
+}
+
+/// Show everything moderators know about a member
+#[command(slash_command, guild_only, required_permissions = "MODERATE_MEMBERS")]
+pub async fn userinfo(
+    ctx: Context<'_>,
+    #[description = "The member to show"] user: serenity::User,
+) -> Result<(), Error> {
+    // fetching the member can take a moment, and everything below is for moderators only
+    ctx.defer_ephemeral().await?;
+
+    let guild_id = ctx.guild_id().unwrap();
+    let (warnings, cases, notes) = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        (
+            warnings::get_warnings(&conn, guild_id.0 as i64, user.id.0 as i64),
+            moderation::get_cases(&conn, guild_id.0 as i64, user.id.0 as i64),
+            get_notes(&conn, guild_id.0 as i64, user.id.0 as i64),
+        )
+    };
+    // they might have left or been banned, everything else is still worth showing then
+    let member = guild_id.member(ctx.discord(), user.id).await.ok();
+
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title(user.tag())
+        .colour(USERINFO_COLOUR)
+        .thumbnail(user.face())
+        .field("Account created", describe_timestamp(user.created_at()), true)
+        .footer(|f| f.text(format!("User ID: {}", user.id)))
+        .timestamp(Utc::now());
+
+    match &member {
+        Some(member) => {
+            let joined = member.joined_at.map_or("unknown".to_string(), describe_timestamp);
+            let roles = if member.roles.is_empty() {
+                "none".to_string()
+            } else {
+                member.roles.iter().map(|r| r.mention().to_string()).collect::<Vec<_>>().join(" ")
+            };
+            embed
+                .field("Joined", joined, true)
+                .field("Roles", modlog::truncate(&roles, 1024), false);
+        },
+        None => {
+            embed.field("Joined", "not a member of this server", true);
+        },
+    }
+
+    let active_warnings = warnings.iter().filter(|w| w.active).count();
+    embed
+        .field(format!("Warnings ({} active, {} total)", active_warnings, warnings.len()), recent_field(&warnings, warnings::describe_warning), false)
+        .field(format!("Cases ({})", cases.len()), recent_field(&cases, moderation::describe_case), false)
+        .field(format!("Notes ({})", notes.len()), recent_field(&notes, describe_note), false);
+
+    ctx.send(|cr| cr
+        .embed(|e| {
+            *e = embed;
+            e
+        })
+        .ephemeral(true)
+    ).await?;
+
+    Ok(())
+}
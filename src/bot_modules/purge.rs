@@ -0,0 +1,193 @@
+use chrono::Utc;
+use poise::{command, serenity_prelude::{self as serenity, Mentionable}};
+use regex::{Regex, RegexBuilder};
+
+use crate::bot_modules::modlog::{self, CachedMessage};
+use crate::{Context, Error};
+
+/// How many messages are looked at at most, matching or not.
+const MAX_SCANNED: usize = 1000;
+/// Discord only bulk deletes messages younger than two weeks, this leaves some leeway.
+const BULK_DELETE_MAX_AGE_SECS: i64 = 14 * 24 * 60 * 60 - 60;
+const PURGE_COLOUR: u32 = 0xed4245;
+
+/// Which messages a purge deletes.
+struct PurgeFilter {
+    user: Option<serenity::UserId>,
+    bots: bool,
+    regex: Option<Regex>,
+    attachments: Option<bool>,
+}
+
+impl PurgeFilter {
+    fn matches(&self, message: &serenity::Message) -> bool {
+        if message.pinned {
+            return false;
+        }
+        if self.user.map_or(false, |user| message.author.id != user) {
+            return false;
+        }
+        if self.bots && !message.author.bot {
+            return false;
+        }
+        if self.regex.as_ref().map_or(false, |regex| !regex.is_match(&message.content)) {
+            return false;
+        }
+        if self.attachments.map_or(false, |wanted| message.attachments.is_empty() == wanted) {
+            return false;
+        }
+
+        true
+    }
+
+    fn describe(&self) -> String {
+        let mut filters = Vec::new();
+        if let Some(user) = self.user {
+            filters.push(format!("by {}", user.mention()));
+        }
+        if self.bots {
+            filters.push("by bots".to_string());
+        }
+        if let Some(regex) = &self.regex {
+            filters.push(format!("matching `{}`", regex));
+        }
+        match self.attachments {
+            Some(true) => filters.push("with attachments".to_string()),
+            Some(false) => filters.push("without attachments".to_string()),
+            None => (),
+        }
+
+        if filters.is_empty() {
+            "none".to_string()
+        } else {
+            filters.join(", ")
+        }
+    }
+}
+
+/// Delete recent messages in this channel
+///
+/// Pinned messages and messages older than two weeks are never deleted.
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_MESSAGES")]
+pub async fn purge(
+    ctx: Context<'_>,
+    #[description = "How many messages to delete"] #[min = 1] #[max = 500] amount: u32,
+    #[description = "Only delete messages by this user"] user: Option<serenity::User>,
+    #[description = "Only delete messages by bots"] bots: Option<bool>,
+    #[description = "Only delete messages matching this regex"] regex: Option<String>,
+    #[description = "Only delete messages with (true) or without (false) attachments"] attachments: Option<bool>,
+    #[description = "Only delete messages sent after the message with this ID"] since: Option<String>,
+) -> Result<(), Error> {
+    let regex = match regex.map(|r| RegexBuilder::new(&r).size_limit(1 << 16).build()).transpose() {
+        Ok(regex) => regex,
+        Err(e) => {
+            ctx.say(format!("That regex doesn't work: {}", e)).await?;
+            return Ok(());
+        },
+    };
+    let since = match since.map(|id| id.trim().parse::<u64>().map(serenity::MessageId)).transpose() {
+        Ok(since) => since,
+        Err(_) => {
+            ctx.say("That's not a message ID.").await?;
+            return Ok(());
+        },
+    };
+    let amount = amount as usize;
+    let filter = PurgeFilter {
+        user: user.map(|u| u.id),
+        bots: bots.unwrap_or(false),
+        regex,
+        attachments,
+    };
+
+    ctx.defer_ephemeral().await?;
+
+    let channel_id = ctx.channel_id();
+    // the message invoking a prefix command shouldn't count towards the amount
+    let invocation = match ctx {
+        poise::Context::Prefix(prefix) => Some(prefix.msg.id),
+        _ => None,
+    };
+    let oldest_allowed = Utc::now().timestamp() - BULK_DELETE_MAX_AGE_SECS;
+
+    let mut matching: Vec<serenity::Message> = Vec::new();
+    let mut scanned = 0;
+    let mut before = invocation;
+    let mut too_old = false;
+    'scan: while matching.len() < amount && scanned < MAX_SCANNED {
+        let batch = channel_id.messages(ctx.discord(), |r| {
+            if let Some(before) = before {
+                r.before(before);
+            }
+            r.limit(100)
+        }).await?;
+        if batch.is_empty() {
+            break;
+        }
+
+        for message in batch {
+            scanned += 1;
+            before = Some(message.id);
+            if since.map_or(false, |since| message.id <= since) {
+                break 'scan;
+            }
+            if message.timestamp.unix_timestamp() < oldest_allowed {
+                too_old = true;
+                break 'scan;
+            }
+            if Some(message.id) != invocation && filter.matches(&message) {
+                matching.push(message);
+                if matching.len() >= amount {
+                    break 'scan;
+                }
+            }
+        }
+    }
+
+    if matching.is_empty() {
+        ctx.say("No messages matched, nothing was deleted.").await?;
+        return Ok(());
+    }
+
+    {
+        // the transcript below replaces the usual deletion log
+        let mut cache = ctx.data().message_cache.lock().await;
+        for message in &matching {
+            cache.skip_deletion_log(message.id);
+        }
+    }
+    let ids: Vec<serenity::MessageId> = matching.iter().map(|m| m.id).collect();
+    for chunk in ids.chunks(100) {
+        channel_id.delete_messages(ctx.discord(), chunk).await?;
+    }
+
+    matching.reverse();
+    let transcript = matching
+        .iter()
+        .map(|m| modlog::transcript_line(&CachedMessage::from_message(m)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title("Messages purged")
+        .colour(PURGE_COLOUR)
+        .description(format!(
+            "{} purged {} messages in {}",
+            ctx.author().mention(),
+            matching.len(),
+            channel_id.mention()
+        ))
+        .field("Filters", filter.describe(), false)
+        .footer(|f| f.text(format!("Moderator ID: {}", ctx.author().id)))
+        .timestamp(Utc::now());
+    let file = (format!("purge-{}.txt", channel_id), transcript.into_bytes());
+    modlog::post(ctx.discord(), ctx.data(), ctx.guild_id().unwrap(), embed, Some(file)).await;
+
+    let mut reply = format!("Deleted {} messages.", matching.len());
+    if too_old && matching.len() < amount {
+        reply.push_str(" Messages older than two weeks can't be purged, so it stopped there.");
+    }
+    ctx.say(reply).await?;
+
+    Ok(())
+}
@@ -0,0 +1,649 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use poise::{command, serenity_prelude::{self as serenity, Mentionable}};
+
+use crate::bot_modules::modlog;
+use crate::guild_settings::{ensure_guild_settings, get_all_guild_settings, get_guild_settings};
+use crate::models::{GuildSettings, RaidSettingsChanges};
+use crate::{Context, Data, Error};
+
+const RAID_COLOUR: u32 = 0xed4245;
+const LOCKDOWN_LIFTED_COLOUR: u32 = 0x57f287;
+/// The guild feature that pauses invites.
+const INVITES_DISABLED: &str = "INVITES_DISABLED";
+/// How many joins per guild are remembered at most, a raid is obvious long before that.
+const MAX_TRACKED_JOINS: usize = 500;
+/// How many joins are listed in an alert.
+const LISTED_JOINS: usize = 20;
+
+/// What happens when a raid is detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Lockdown {
+    // Don't lock the server down, only alert the moderators.
+    #[name = "alert only"]
+    Off,
+    // Raise the verification level to the highest one.
+    #[name = "verification"]
+    Verification,
+    // Pause all invites.
+    #[name = "pause invites"]
+    Invites,
+    // Kick everyone who joins.
+    #[name = "kick"]
+    Kick,
+}
+
+impl Lockdown {
+    /// The name under which this mode is stored in the database.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Lockdown::Off => "off",
+            Lockdown::Verification => "verification",
+            Lockdown::Invites => "invites",
+            Lockdown::Kick => "kick",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Lockdown::Off),
+            "verification" => Some(Lockdown::Verification),
+            "invites" => Some(Lockdown::Invites),
+            "kick" => Some(Lockdown::Kick),
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Lockdown::Off => "no lockdown",
+            Lockdown::Verification => "the highest verification level",
+            Lockdown::Invites => "paused invites",
+            Lockdown::Kick => "kicking everyone who joins",
+        }
+    }
+}
+
+/// The raid protection settings of a guild, and whether it's locked down right now.
+#[derive(Debug, Clone)]
+pub struct RaidConfig {
+    pub detect: bool,
+    pub window: Duration,
+    pub max_joins: usize,
+    pub max_suspicious: usize,
+    pub new_account_secs: i64,
+    pub lockdown: Lockdown,
+    pub active_lockdown: Option<Lockdown>,
+}
+
+impl RaidConfig {
+    fn new(settings: &GuildSettings) -> Self {
+        RaidConfig {
+            detect: settings.raid_enabled,
+            window: Duration::from_secs(settings.raid_window_secs.max(1) as u64),
+            max_joins: settings.raid_max_joins.max(1) as usize,
+            max_suspicious: settings.raid_max_suspicious.max(1) as usize,
+            new_account_secs: settings.raid_new_account_secs as i64,
+            lockdown: Lockdown::from_name(&settings.raid_lockdown).unwrap_or(Lockdown::Off),
+            active_lockdown: settings.lockdown_mode.as_deref().and_then(Lockdown::from_name),
+        }
+    }
+}
+
+/// A username without the decorations raiders use to tell their accounts apart.
+fn name_skeleton(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .to_string()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Whether two name skeletons look like they were made from the same template.
+fn similar_names(a: &str, b: &str) -> bool {
+    let shortest = a.chars().count().min(b.chars().count());
+    if shortest < 3 {
+        return false;
+    }
+
+    a == b || (shortest >= 5 && edit_distance(a, b) <= shortest / 5)
+}
+
+/// What's remembered of a recent join to tell whether it's part of a raid.
+struct RecentJoin {
+    at: Instant,
+    user_id: serenity::UserId,
+    tag: String,
+    skeleton: String,
+    new_account: bool,
+    /// How many of the other tracked joins have a similar name, kept up to date as joins come and go.
+    similar: usize,
+}
+
+/// The recent joins of a guild that look like a raid.
+struct RaidReport {
+    joins: Vec<(serenity::UserId, String, bool)>,
+    suspicious: usize,
+}
+
+/// The recent joins of every guild.
+#[derive(Default)]
+pub struct JoinTracker {
+    recent: HashMap<serenity::GuildId, VecDeque<RecentJoin>>,
+    last_report: HashMap<serenity::GuildId, Instant>,
+}
+
+impl JoinTracker {
+    /// Remember a join and check whether the joins within the window look like a raid.
+    ///
+    /// A raid is only reported once per window, so an ongoing one doesn't flood the log.
+    ///
+    /// Names are only compared when a join comes or goes, so a big raid doesn't compare every
+    /// tracked name with every other one on each join.
+    fn record(&mut self, guild_id: serenity::GuildId, mut join: RecentJoin, config: &RaidConfig) -> Option<RaidReport> {
+        let now = join.at;
+        let joins = self.recent.entry(guild_id).or_default();
+        while joins.front().map_or(false, |j| now.duration_since(j.at) >= config.window) || joins.len() >= MAX_TRACKED_JOINS {
+            let Some(forgotten) = joins.pop_front() else {
+                break;
+            };
+            if forgotten.similar > 0 {
+                for other in joins.iter_mut().filter(|other| similar_names(&forgotten.skeleton, &other.skeleton)) {
+                    other.similar = other.similar.saturating_sub(1);
+                }
+            }
+        }
+        for other in joins.iter_mut() {
+            if similar_names(&join.skeleton, &other.skeleton) {
+                other.similar += 1;
+                join.similar += 1;
+            }
+        }
+        joins.push_back(join);
+
+        let suspicious: Vec<bool> = joins.iter().map(|join| join.new_account || join.similar > 0).collect();
+        let suspicious_count = suspicious.iter().filter(|s| **s).count();
+        if joins.len() <= config.max_joins && suspicious_count <= config.max_suspicious {
+            return None;
+        }
+
+        if self.last_report.get(&guild_id).map_or(false, |at| now.duration_since(*at) < config.window) {
+            return None;
+        }
+        self.last_report.insert(guild_id, now);
+
+        Some(RaidReport {
+            joins: joins.iter().zip(suspicious).map(|(j, s)| (j.user_id, j.tag.clone(), s)).collect(),
+            suspicious: suspicious_count,
+        })
+    }
+}
+
+fn update_raid_settings(conn: &SqliteConnection, of_guild: i64, changes: &RaidSettingsChanges) {
+    use crate::schema::guild_settings::dsl::*;
+
+    ensure_guild_settings(conn, of_guild);
+    diesel::update(guild_settings.filter(guild_id.eq(of_guild)))
+        .set(changes)
+        .execute(conn)
+        .expect("Error updating guild settings");
+}
+
+/// Load the raid protection settings of all guilds that have it enabled or are locked down.
+pub fn load_raid_configs(conn: &SqliteConnection) -> HashMap<serenity::GuildId, RaidConfig> {
+    get_all_guild_settings(conn)
+        .into_iter()
+        .filter(|settings| settings.raid_enabled || settings.lockdown_mode.is_some())
+        .map(|settings| (serenity::GuildId(settings.guild_id as u64), RaidConfig::new(&settings)))
+        .collect()
+}
+
+/// Reload the raid protection cache, so changes to the settings take effect immediately.
+pub async fn reload_raid_configs(data: &Data) {
+    let configs = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        load_raid_configs(&conn)
+    };
+    *data.raid_protection.write().await = configs;
+}
+
+fn verification_level_number(level: serenity::VerificationLevel) -> i32 {
+    match level {
+        serenity::VerificationLevel::Low => 1,
+        serenity::VerificationLevel::Medium => 2,
+        serenity::VerificationLevel::High => 3,
+        serenity::VerificationLevel::Higher => 4,
+        _ => 0,
+    }
+}
+
+fn verification_level_from_number(number: i32) -> serenity::VerificationLevel {
+    match number {
+        1 => serenity::VerificationLevel::Low,
+        2 => serenity::VerificationLevel::Medium,
+        3 => serenity::VerificationLevel::High,
+        4 => serenity::VerificationLevel::Higher,
+        _ => serenity::VerificationLevel::None,
+    }
+}
+
+/// Pause or resume invites. Serenity can't edit guild features, so this goes through the HTTP API.
+async fn set_invites_paused(ctx: &serenity::Context, guild_id: serenity::GuildId, paused: bool) -> serenity::Result<()> {
+    let Some(mut features) = ctx.cache.guild_field(guild_id, |g| g.features.clone()) else {
+        return Err(serenity::Error::Other("The server isn't cached"));
+    };
+    features.retain(|f| f != INVITES_DISABLED);
+    if paused {
+        features.push(INVITES_DISABLED.to_string());
+    }
+
+    let mut map = serenity::json::JsonMap::new();
+    map.insert("features".to_string(), serenity::json::Value::from(features));
+    ctx.http.edit_guild(guild_id.0, &map, Some("Raid lockdown")).await?;
+
+    Ok(())
+}
+
+/// Lock a guild down and remember how, so it can be undone.
+pub async fn start_lockdown(ctx: &serenity::Context, data: &Data, guild_id: serenity::GuildId, mode: Lockdown) -> serenity::Result<()> {
+    let mut previous_verification = None;
+    match mode {
+        Lockdown::Verification => {
+            let Some(level) = ctx.cache.guild_field(guild_id, |g| g.verification_level) else {
+                return Err(serenity::Error::Other("The server isn't cached"));
+            };
+            previous_verification = Some(verification_level_number(level));
+            guild_id.edit(ctx, |g| g.verification_level(serenity::VerificationLevel::Higher)).await?;
+        },
+        Lockdown::Invites => set_invites_paused(ctx, guild_id, true).await?,
+        Lockdown::Kick | Lockdown::Off => (),
+    }
+
+    {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        update_raid_settings(&conn, guild_id.0 as i64, &RaidSettingsChanges {
+            lockdown_mode: Some(Some(mode.as_str())),
+            lockdown_previous_verification: Some(previous_verification),
+            ..Default::default()
+        });
+    }
+    reload_raid_configs(data).await;
+
+    Ok(())
+}
+
+/// Undo a lockdown. Returns whether the guild was locked down at all.
+pub async fn end_lockdown(ctx: &serenity::Context, data: &Data, guild_id: serenity::GuildId) -> serenity::Result<bool> {
+    let settings = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        get_guild_settings(&conn, guild_id.0 as i64)
+    };
+    let Some(mode) = settings.lockdown_mode.as_deref().and_then(Lockdown::from_name) else {
+        return Ok(false);
+    };
+
+    match mode {
+        Lockdown::Verification => {
+            let level = verification_level_from_number(settings.lockdown_previous_verification.unwrap_or(0));
+            guild_id.edit(ctx, |g| g.verification_level(level)).await?;
+        },
+        Lockdown::Invites => set_invites_paused(ctx, guild_id, false).await?,
+        Lockdown::Kick | Lockdown::Off => (),
+    }
+
+    {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        update_raid_settings(&conn, guild_id.0 as i64, &RaidSettingsChanges {
+            lockdown_mode: Some(None),
+            lockdown_previous_verification: Some(None),
+            ..Default::default()
+        });
+    }
+    reload_raid_configs(data).await;
+
+    Ok(true)
+}
+
+/// Kick a member that joined during a lockdown, telling them to come back later.
+async fn kick_during_lockdown(ctx: &serenity::Context, member: &serenity::Member) {
+    let guild_name = member.guild_id.name(ctx).unwrap_or_else(|| "The server".to_string());
+    let notice = format!("{} is locked down right now, please try joining again later.", guild_name);
+    let _ = member.user.direct_message(ctx, |m| m.content(notice)).await;
+
+    if let Err(e) = member.guild_id.kick_with_reason(ctx, member.user.id, "Raid lockdown").await {
+        warn!("Couldn't kick {} during a lockdown: {}", member.user.tag(), e);
+    }
+}
+
+/// Check new members for signs of a raid and lock the guild down if it's configured to.
+///
+/// Returns whether the member was kicked because of a lockdown.
+pub async fn handle_member_addition(ctx: &serenity::Context, data: &Data, member: &serenity::Member) -> bool {
+    let guild_id = member.guild_id;
+    let Some(config) = data.raid_protection.read().await.get(&guild_id).cloned() else {
+        return false;
+    };
+    if config.active_lockdown == Some(Lockdown::Kick) {
+        kick_during_lockdown(ctx, member).await;
+        return true;
+    }
+    if !config.detect {
+        return false;
+    }
+
+    let account_age = Utc::now().timestamp() - member.user.created_at().unix_timestamp();
+    let join = RecentJoin {
+        at: Instant::now(),
+        user_id: member.user.id,
+        tag: member.user.tag(),
+        skeleton: name_skeleton(&member.user.name),
+        new_account: account_age < config.new_account_secs,
+        similar: 0,
+    };
+    let Some(report) = data.join_tracker.lock().await.record(guild_id, join, &config) else {
+        return false;
+    };
+
+    let mut kicked = false;
+    let lockdown = match (config.active_lockdown, config.lockdown) {
+        (Some(active), _) => format!("Already locked down with {}.", active.describe()),
+        (None, Lockdown::Off) => "No lockdown is configured, use `/lockdown on` to start one.".to_string(),
+        (None, mode) => match start_lockdown(ctx, data, guild_id, mode).await {
+            Ok(()) => {
+                if mode == Lockdown::Kick {
+                    kick_during_lockdown(ctx, member).await;
+                    kicked = true;
+                }
+                format!("Locked down with {}, use `/lockdown off` to lift it.", mode.describe())
+            },
+            Err(e) => {
+                warn!("Couldn't start a lockdown: {}", e);
+                format!("Starting a lockdown with {} failed: {}", mode.describe(), e)
+            },
+        },
+    };
+
+    let mut joins = report.joins
+        .iter()
+        .rev()
+        .take(LISTED_JOINS)
+        .map(|(user_id, tag, suspicious)| format!("{} {} ({}){}", if *suspicious { "⚠️" } else { "•" }, user_id.mention(), tag, if *suspicious { " suspicious" } else { "" }))
+        .collect::<Vec<_>>();
+    if report.joins.len() > LISTED_JOINS {
+        joins.push(format!("…and {} more", report.joins.len() - LISTED_JOINS));
+    }
+
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title("Possible raid")
+        .colour(RAID_COLOUR)
+        .description(format!(
+            "{} members joined within {} seconds, {} of them have new accounts or similar names.",
+            report.joins.len(),
+            config.window.as_secs(),
+            report.suspicious
+        ))
+        .field("Recent joins", modlog::truncate(&joins.join("\n"), 1024), false)
+        .field("Lockdown", lockdown, false)
+        .timestamp(Utc::now());
+    modlog::post(ctx, data, guild_id, embed, None).await;
+
+    kicked
+}
+
+/// Configure the detection of raids
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("raid_settings", "raid_toggle", "raid_limits", "raid_lockdown")
+)]
+pub async fn raid(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show the raid protection settings of this server
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "settings")]
+pub async fn raid_settings(ctx: Context<'_>) -> Result<(), Error> {
+    let settings = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        get_guild_settings(&conn, ctx.guild_id().unwrap().0 as i64)
+    };
+    let lockdown = Lockdown::from_name(&settings.raid_lockdown).unwrap_or(Lockdown::Off);
+    let active = settings.lockdown_mode.as_deref().and_then(Lockdown::from_name);
+
+    ctx.say(format!(
+        "Raid detection is **{}**.\nA raid is more than {} joins, or more than {} suspicious joins, within {} seconds. Accounts younger than {} days and similar names are suspicious.\nOn a raid: {}\nCurrent lockdown: {}",
+        if settings.raid_enabled { "enabled" } else { "disabled" },
+        settings.raid_max_joins,
+        settings.raid_max_suspicious,
+        settings.raid_window_secs,
+        settings.raid_new_account_secs / (24 * 60 * 60),
+        lockdown.describe(),
+        active.map_or("none", |a| a.describe())
+    )).await?;
+
+    Ok(())
+}
+
+/// Turn raid detection on or off
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "toggle")]
+pub async fn raid_toggle(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let enabled = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        let enabled = !get_guild_settings(&conn, guild_id).raid_enabled;
+        update_raid_settings(&conn, guild_id, &RaidSettingsChanges {
+            raid_enabled: Some(enabled),
+            ..Default::default()
+        });
+        enabled
+    };
+    reload_raid_configs(ctx.data()).await;
+
+    ctx.say(format!("Raid detection is now {}.", if enabled { "enabled" } else { "disabled" })).await?;
+
+    Ok(())
+}
+
+/// Set what counts as a raid
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "limits")]
+pub async fn raid_limits(
+    ctx: Context<'_>,
+    #[description = "How many seconds back joins are counted"] #[min = 10] #[max = 3600] window_seconds: Option<i32>,
+    #[description = "How many members may join within the window"] #[min = 2] #[max = 500] joins: Option<i32>,
+    #[description = "How many suspicious members may join within the window"] #[min = 1] #[max = 500] suspicious: Option<i32>,
+    #[description = "Accounts younger than this many days are suspicious"] #[min = 0] #[max = 365] new_account_days: Option<i32>,
+) -> Result<(), Error> {
+    if window_seconds.is_none() && joins.is_none() && suspicious.is_none() && new_account_days.is_none() {
+        ctx.say("Give at least one limit to change.").await?;
+        return Ok(());
+    }
+
+    {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        update_raid_settings(&conn, ctx.guild_id().unwrap().0 as i64, &RaidSettingsChanges {
+            raid_window_secs: window_seconds,
+            raid_max_joins: joins,
+            raid_max_suspicious: suspicious,
+            raid_new_account_secs: new_account_days.map(|d| d * 24 * 60 * 60),
+            ..Default::default()
+        });
+    }
+    reload_raid_configs(ctx.data()).await;
+
+    ctx.say("The raid detection limits were updated.").await?;
+
+    Ok(())
+}
+
+/// Set how the server is locked down when a raid is detected
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "lockdown")]
+pub async fn raid_lockdown(
+    ctx: Context<'_>,
+    #[description = "What to do when a raid is detected"] mode: Lockdown,
+) -> Result<(), Error> {
+    {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        update_raid_settings(&conn, ctx.guild_id().unwrap().0 as i64, &RaidSettingsChanges {
+            raid_lockdown: Some(mode.as_str()),
+            ..Default::default()
+        });
+    }
+    reload_raid_configs(ctx.data()).await;
+
+    ctx.say(format!("Raids now lead to {}.", mode.describe())).await?;
+
+    Ok(())
+}
+
+/// Lock the server down or lift a lockdown
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("lockdown_on", "lockdown_off")
+)]
+pub async fn lockdown(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Lock the server down right away
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "on")]
+pub async fn lockdown_on(
+    ctx: Context<'_>,
+    #[description = "How to lock down (default: what raids lead to)"] mode: Option<Lockdown>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let settings = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        get_guild_settings(&conn, guild_id.0 as i64)
+    };
+    if let Some(active) = settings.lockdown_mode.as_deref().and_then(Lockdown::from_name) {
+        ctx.say(format!("The server already is locked down with {}.", active.describe())).await?;
+        return Ok(());
+    }
+    let mode = mode.or_else(|| Lockdown::from_name(&settings.raid_lockdown)).unwrap_or(Lockdown::Off);
+    if mode == Lockdown::Off {
+        ctx.say("Choose how to lock down, no lockdown is configured for raids.").await?;
+        return Ok(());
+    }
+
+    start_lockdown(ctx.discord(), ctx.data(), guild_id, mode).await?;
+
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title("Lockdown started")
+        .colour(RAID_COLOUR)
+        .description(format!("{} locked the server down with {}", ctx.author().mention(), mode.describe()))
+        .footer(|f| f.text(format!("Moderator ID: {}", ctx.author().id)))
+        .timestamp(Utc::now());
+    modlog::post(ctx.discord(), ctx.data(), guild_id, embed, None).await;
+
+    ctx.say(format!("The server is now locked down with {}.", mode.describe())).await?;
+
+    Ok(())
+}
+
+/// Lift the lockdown of the server
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "off")]
+pub async fn lockdown_off(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    if !end_lockdown(ctx.discord(), ctx.data(), guild_id).await? {
+        ctx.say("The server isn't locked down.").await?;
+        return Ok(());
+    }
+
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title("Lockdown lifted")
+        .colour(LOCKDOWN_LIFTED_COLOUR)
+        .description(format!("{} lifted the lockdown", ctx.author().mention()))
+        .footer(|f| f.text(format!("Moderator ID: {}", ctx.author().id)))
+        .timestamp(Utc::now());
+    modlog::post(ctx.discord(), ctx.data(), guild_id, embed, None).await;
+
+    ctx.say("The lockdown was lifted.").await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_similar_names() {
+        assert_eq!(name_skeleton("Free_Nitro_1234"), "freenitro");
+        assert!(similar_names(&name_skeleton("FreeNitro01"), &name_skeleton("free.nitro.77")));
+        assert!(similar_names(&name_skeleton("crypto_giveaway"), &name_skeleton("crypt0_giveaway")));
+        assert!(!similar_names(&name_skeleton("alice"), &name_skeleton("bob")));
+        // short names are too likely to match by chance
+        assert!(!similar_names(&name_skeleton("ab1"), &name_skeleton("ab2")));
+    }
+
+    #[test]
+    fn test_join_tracker() {
+        let config = RaidConfig {
+            detect: true,
+            window: Duration::from_secs(60),
+            max_joins: 5,
+            max_suspicious: 2,
+            new_account_secs: 0,
+            lockdown: Lockdown::Off,
+            active_lockdown: None,
+        };
+        let guild = serenity::GuildId(1);
+        let start = Instant::now();
+        let join = |id: u64, secs: u64, name: &str| RecentJoin {
+            at: start + Duration::from_secs(secs),
+            user_id: serenity::UserId(id),
+            tag: format!("{}#0001", name),
+            skeleton: name_skeleton(name),
+            new_account: false,
+            similar: 0,
+        };
+        let mut tracker = JoinTracker::default();
+
+        // ordinary joins spread out over time
+        for (id, name) in ["alice", "bob", "carol", "dave", "erin", "frank"].iter().enumerate() {
+            assert!(tracker.record(guild, join(id as u64, id as u64 * 30, name), &config).is_none());
+        }
+
+        // a burst of similar names
+        assert!(tracker.record(guild, join(10, 200, "spambot_1"), &config).is_none());
+        assert!(tracker.record(guild, join(11, 201, "spambot_2"), &config).is_none());
+        let report = tracker.record(guild, join(12, 202, "spambot_3"), &config).unwrap();
+        assert_eq!(report.suspicious, 3);
+
+        // the same raid isn't reported twice
+        assert!(tracker.record(guild, join(13, 203, "spambot_4"), &config).is_none());
+
+        // once the burst is out of the window, the last of its names has nothing similar left
+        assert!(tracker.record(guild, join(14, 262, "grace"), &config).is_none());
+        let joins = &tracker.recent[&guild];
+        assert_eq!(joins.iter().find(|j| j.user_id.0 == 13).unwrap().similar, 0);
+    }
+}
@@ -0,0 +1,520 @@
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use poise::{command, serenity_prelude::{self as serenity, Mentionable}};
+
+use crate::bot_modules::{moderation, modlog};
+use crate::models::{NewWarning, NewWarningEscalation, Warning, WarningEscalation};
+use crate::pagination::paginate;
+use crate::{Context, Data, Error};
+
+const WARNINGS_PER_PAGE: usize = 10;
+const WARNING_COLOUR: u32 = 0xfee75c;
+
+/// What happens to a member once they reach a number of active warnings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Escalation {
+    // Time the member out.
+    #[name = "timeout"]
+    Timeout,
+    // Kick the member from the server.
+    #[name = "kick"]
+    Kick,
+}
+
+impl Escalation {
+    /// The name under which this escalation is stored in the database.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Escalation::Timeout => "timeout",
+            Escalation::Kick => "kick",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "timeout" => Some(Escalation::Timeout),
+            "kick" => Some(Escalation::Kick),
+            _ => None,
+        }
+    }
+}
+
+fn create_warning(conn: &SqliteConnection, new_warning: &NewWarning) -> Warning {
+    use crate::schema::warnings::dsl::*;
+
+    diesel::insert_into(warnings)
+        .values(new_warning)
+        .execute(conn)
+        .expect("Error creating warning");
+
+    warnings.order(warning_id.desc()).first(conn).unwrap()
+}
+
+fn get_warning(conn: &SqliteConnection, of_guild: i64, to_get: i32) -> Option<Warning> {
+    use crate::schema::warnings::dsl::*;
+
+    warnings
+        .filter(guild_id.eq(of_guild))
+        .filter(warning_id.eq(to_get))
+        .first(conn)
+        .ok()
+}
+
+/// All warnings of a member, the newest first.
+pub fn get_warnings(conn: &SqliteConnection, of_guild: i64, of_user: i64) -> Vec<Warning> {
+    use crate::schema::warnings::dsl::*;
+
+    warnings
+        .filter(guild_id.eq(of_guild))
+        .filter(user_id.eq(of_user))
+        .order(warning_id.desc())
+        .load(conn)
+        .expect("Error loading warnings")
+}
+
+fn count_active_warnings(conn: &SqliteConnection, of_guild: i64, of_user: i64) -> QueryResult<i64> {
+    use crate::schema::warnings::dsl::*;
+
+    warnings
+        .filter(guild_id.eq(of_guild))
+        .filter(user_id.eq(of_user))
+        .filter(active.eq(true))
+        .count()
+        .get_result(conn)
+}
+
+/// The warning counts of the escalations applied to a member, for as long as the warnings that
+/// triggered them are active.
+fn get_applied_escalations(conn: &SqliteConnection, of_guild: i64, of_user: i64) -> QueryResult<Vec<i32>> {
+    use crate::schema::warnings::dsl::*;
+
+    let applied: Vec<Option<i32>> = warnings
+        .filter(guild_id.eq(of_guild))
+        .filter(user_id.eq(of_user))
+        .filter(active.eq(true))
+        .select(escalation_count)
+        .load(conn)?;
+
+    Ok(applied.into_iter().flatten().collect())
+}
+
+fn set_warning_escalation(conn: &SqliteConnection, to_update: i32, of_count: i32) -> QueryResult<()> {
+    use crate::schema::warnings::dsl::*;
+
+    diesel::update(warnings.filter(warning_id.eq(to_update)))
+        .set(escalation_count.eq(of_count))
+        .execute(conn)
+        .map(|_| ())
+}
+
+fn deactivate_warning(conn: &SqliteConnection, to_update: i32) {
+    use crate::schema::warnings::dsl::*;
+
+    diesel::update(warnings.filter(warning_id.eq(to_update)))
+        .set(active.eq(false))
+        .execute(conn)
+        .expect("Error updating warning");
+}
+
+fn get_escalations(conn: &SqliteConnection, of_guild: i64) -> QueryResult<Vec<WarningEscalation>> {
+    use crate::schema::warning_escalations::dsl::*;
+
+    warning_escalations
+        .filter(guild_id.eq(of_guild))
+        .order(warning_count.asc())
+        .load(conn)
+}
+
+fn remove_escalation(conn: &SqliteConnection, of_guild: i64, of_count: i32) -> bool {
+    use crate::schema::warning_escalations::dsl::*;
+
+    let deleted = diesel::delete(warning_escalations
+        .filter(guild_id.eq(of_guild))
+        .filter(warning_count.eq(of_count)))
+        .execute(conn)
+        .expect("Error deleting warning escalation");
+
+    deleted > 0
+}
+
+fn set_escalation(conn: &SqliteConnection, escalation: &NewWarningEscalation) {
+    use crate::schema::warning_escalations;
+
+    remove_escalation(conn, escalation.guild_id, escalation.warning_count);
+    diesel::insert_into(warning_escalations::table)
+        .values(escalation)
+        .execute(conn)
+        .expect("Error inserting warning escalation");
+}
+
+/// A one line summary of a warning.
+pub fn describe_warning(warning: &Warning) -> String {
+    let line = format!(
+        "`#{}` <t:{}:d> by {}: {}",
+        warning.warning_id,
+        warning.created_at.timestamp(),
+        serenity::UserId(warning.moderator_id as u64).mention(),
+        warning.reason
+    );
+
+    if warning.active {
+        line
+    } else {
+        format!("~~{}~~ (removed)", line)
+    }
+}
+
+fn describe_escalation(escalation: &WarningEscalation) -> String {
+    match Escalation::from_name(&escalation.action) {
+        Some(Escalation::Timeout) => format!("timeout for {} minutes", escalation.timeout_secs / 60),
+        Some(Escalation::Kick) => "kick".to_string(),
+        None => format!("unknown action `{}`", escalation.action),
+    }
+}
+
+/// The escalation a member is due for: the highest one they reached, unless it was applied already.
+///
+/// Taking the highest one reached instead of the one for their exact count means escalations added
+/// after a member passed them and counts skipped by warnings issued at the same time still apply.
+fn due_escalation(escalations: Vec<WarningEscalation>, active: i64, applied: &[i32]) -> Option<WarningEscalation> {
+    escalations
+        .into_iter()
+        .filter(|e| e.warning_count as i64 <= active)
+        .max_by_key(|e| e.warning_count)
+        .filter(|e| !applied.contains(&e.warning_count))
+}
+
+/// Find the escalation a member is due for and mark it as applied by a warning.
+///
+/// This happens in one transaction, so warnings issued at the same time don't both apply it.
+fn claim_escalation(
+    conn: &SqliteConnection,
+    of_guild: i64,
+    of_user: i64,
+    by_warning: i32,
+) -> QueryResult<(i64, Option<WarningEscalation>)> {
+    conn.immediate_transaction(|| {
+        let active = count_active_warnings(conn, of_guild, of_user)?;
+        let applied = get_applied_escalations(conn, of_guild, of_user)?;
+        let escalation = due_escalation(get_escalations(conn, of_guild)?, active, &applied);
+        if let Some(escalation) = &escalation {
+            set_warning_escalation(conn, by_warning, escalation.warning_count)?;
+        }
+
+        Ok((active, escalation))
+    })
+}
+
+/// Apply an escalation to a member, recording it as a case, and describe what happened.
+async fn escalate(
+    ctx: &serenity::Context,
+    data: &Data,
+    guild_id: serenity::GuildId,
+    user: &serenity::User,
+    moderator_id: serenity::UserId,
+    escalation: &WarningEscalation,
+    active: i64,
+) -> String {
+    let reason = format!("Reached {} active warnings", active);
+    let result = match Escalation::from_name(&escalation.action) {
+        Some(Escalation::Timeout) => {
+            moderation::apply_timeout(ctx, data, guild_id, user, moderator_id, escalation.timeout_secs as i64, &reason).await
+        },
+        Some(Escalation::Kick) => moderation::apply_kick(ctx, data, guild_id, user, moderator_id, &reason).await,
+        None => return format!("{} (not applied)", describe_escalation(escalation)),
+    };
+
+    match result {
+        Ok(case) => format!("{} (case `#{}`)", describe_escalation(escalation), case.case_id),
+        Err(e) => {
+            warn!("Couldn't escalate warnings of {}: {}", user.id, e);
+            format!("{} (failed: {})", describe_escalation(escalation), e)
+        },
+    }
+}
+
+/// Warn a member, tell them about it and log it, then apply the escalation they are due for with
+/// their new number of active warnings, if there is one.
+///
+/// Returns the warning and what the escalation did.
+pub async fn issue_warning(
+    ctx: &serenity::Context,
+    data: &Data,
+    guild_id: serenity::GuildId,
+    user: &serenity::User,
+    moderator_id: serenity::UserId,
+    reason: &str,
+) -> (Warning, Option<String>) {
+    let (warning, active, escalation) = {
+        let conn = data.pool.get().expect("Couldn't get connection from pool");
+        let warning = create_warning(&conn, &NewWarning {
+            guild_id: guild_id.0 as i64,
+            user_id: user.id.0 as i64,
+            moderator_id: moderator_id.0 as i64,
+            reason,
+            created_at: Utc::now().naive_utc(),
+        });
+        let (active, escalation) = claim_escalation(&conn, guild_id.0 as i64, user.id.0 as i64, warning.warning_id)
+            .expect("Error escalating warnings");
+        (warning, active, escalation)
+    };
+
+    // the member might not accept DMs, that's no reason not to warn them
+    let guild_name = guild_id.name(ctx).unwrap_or_else(|| "the server".to_string());
+    let _ = user.direct_message(ctx, |m| m
+        .content(format!("You have been warned in **{}**: {}", guild_name, reason))
+    ).await;
+
+    // escalate after the DM, a kicked member can't always be reached anymore
+    let escalated = match &escalation {
+        Some(escalation) => Some(escalate(ctx, data, guild_id, user, moderator_id, escalation, active).await),
+        None => None,
+    };
+
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title(format!("Warning #{}", warning.warning_id))
+        .colour(WARNING_COLOUR)
+        .description(format!(
+            "{} ({}) was warned by {}, they now have {} active warnings",
+            user.mention(),
+            user.tag(),
+            moderator_id.mention(),
+            active
+        ))
+        .field("Reason", modlog::truncate(reason, 1024), false)
+        .footer(|f| f.text(format!("User ID: {}", user.id)))
+        .timestamp(Utc::now());
+    if let Some(escalated) = &escalated {
+        embed.field("Escalation", escalated, false);
+    }
+    modlog::post(ctx, data, guild_id, embed, None).await;
+
+    (warning, escalated)
+}
+
+/// Warn a member
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MODERATE_MEMBERS")]
+pub async fn warn(
+    ctx: Context<'_>,
+    #[description = "The member to warn"] user: serenity::User,
+    #[description = "Why they are warned"] #[rest] reason: String,
+) -> Result<(), Error> {
+    if user.bot {
+        ctx.say("Bots can't be warned.").await?;
+        return Ok(());
+    }
+    // warnings can escalate to a timeout or kick, so the same hierarchy applies
+    if !moderation::check_hierarchy(ctx, &user).await? {
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().unwrap();
+    let (warning, escalated) = issue_warning(ctx.discord(), ctx.data(), guild_id, &user, ctx.author().id, &reason).await;
+
+    let mut reply = format!("Warned {} (`#{}`): {}", user.mention(), warning.warning_id, reason);
+    if let Some(escalated) = escalated {
+        reply.push_str(&format!("\nThat was one warning too many, escalated to {}.", escalated));
+    }
+    ctx.send(|cr| cr
+        .content(reply)
+        .allowed_mentions(|am| am.empty_parse())
+    ).await?;
+
+    Ok(())
+}
+
+/// Show the warnings of a member
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MODERATE_MEMBERS")]
+pub async fn warnings(
+    ctx: Context<'_>,
+    #[description = "The member whose warnings to show"] user: serenity::User,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let all = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        get_warnings(&conn, guild_id, user.id.0 as i64)
+    };
+
+    if all.is_empty() {
+        ctx.send(|cr| cr
+            .content(format!("{} has no warnings.", user.mention()))
+            .allowed_mentions(|am| am.empty_parse())
+        ).await?;
+        return Ok(());
+    }
+
+    let active = all.iter().filter(|w| w.active).count();
+    let pages = all
+        .chunks(WARNINGS_PER_PAGE)
+        .map(|chunk| {
+            let lines = chunk.iter().map(describe_warning).collect::<Vec<_>>().join("\n");
+            format!("{} active warnings\n\n{}", active, lines)
+        })
+        .collect::<Vec<_>>();
+
+    paginate(ctx, &format!("Warnings of {}", user.tag()), &pages, false).await
+}
+
+/// Remove a warning, it stays in the history but no longer counts
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MODERATE_MEMBERS")]
+pub async fn unwarn(
+    ctx: Context<'_>,
+    #[description = "The ID of the warning"] warning_id: i32,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let warning = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        let warning = get_warning(&conn, guild_id.0 as i64, warning_id);
+        if let Some(warning) = warning.as_ref().filter(|w| w.active) {
+            deactivate_warning(&conn, warning.warning_id);
+        }
+        warning
+    };
+
+    let Some(warning) = warning else {
+        ctx.say("Couldn't find a warning with that ID!").await?;
+        return Ok(());
+    };
+    if !warning.active {
+        ctx.say("That warning was already removed.").await?;
+        return Ok(());
+    }
+
+    let user_id = serenity::UserId(warning.user_id as u64);
+    let mut embed = serenity::CreateEmbed::default();
+    embed
+        .title(format!("Warning #{} removed", warning.warning_id))
+        .colour(WARNING_COLOUR)
+        .description(format!("{} removed a warning of {}", ctx.author().mention(), user_id.mention()))
+        .field("Reason", modlog::truncate(&warning.reason, 1024), false)
+        .footer(|f| f.text(format!("User ID: {}", user_id)))
+        .timestamp(Utc::now());
+    modlog::post(ctx.discord(), ctx.data(), guild_id, embed, None).await;
+
+    ctx.send(|cr| cr
+        .content(format!("Removed warning `#{}` of {}.", warning.warning_id, user_id.mention()))
+        .allowed_mentions(|am| am.empty_parse())
+    ).await?;
+
+    Ok(())
+}
+
+/// Configure what happens once members collect too many warnings
+///
+/// When a member is warned, the highest escalation they reached is applied, unless one of their
+/// active warnings already applied it. Removed warnings no longer count as having applied it.
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("escalation_set", "escalation_remove", "escalation_list")
+)]
+pub async fn escalation(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Set what happens once a member reaches a number of active warnings
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "set")]
+pub async fn escalation_set(
+    ctx: Context<'_>,
+    #[description = "The number of active warnings"] #[min = 1] warnings: i32,
+    #[description = "What happens to the member"] action: Escalation,
+    #[description = "How long timeouts last in minutes (default: 60)"] #[min = 1] #[max = 40320] timeout_minutes: Option<i32>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let escalation = NewWarningEscalation {
+        guild_id,
+        warning_count: warnings,
+        action: action.as_str(),
+        timeout_secs: if action == Escalation::Timeout { timeout_minutes.unwrap_or(60) * 60 } else { 0 },
+    };
+    {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        set_escalation(&conn, &escalation);
+    }
+
+    let description = describe_escalation(&WarningEscalation {
+        escalation_id: 0,
+        guild_id,
+        warning_count: warnings,
+        action: action.as_str().to_string(),
+        timeout_secs: escalation.timeout_secs,
+    });
+    ctx.say(format!("Members reaching {} active warnings now get: {}.", warnings, description)).await?;
+
+    Ok(())
+}
+
+/// Stop escalating at a number of active warnings
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "remove")]
+pub async fn escalation_remove(
+    ctx: Context<'_>,
+    #[description = "The number of active warnings"] warnings: i32,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let removed = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        remove_escalation(&conn, guild_id, warnings)
+    };
+
+    if removed {
+        ctx.say(format!("Nothing happens at {} active warnings anymore.", warnings)).await?;
+    } else {
+        ctx.say(format!("Nothing happens at {} active warnings already.", warnings)).await?;
+    }
+
+    Ok(())
+}
+
+/// Show what happens once members collect too many warnings
+#[command(slash_command, prefix_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "list")]
+pub async fn escalation_list(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap().0 as i64;
+    let escalations = {
+        let conn = ctx.data().pool.get().expect("Couldn't get connection from pool");
+        get_escalations(&conn, guild_id).expect("Error loading warning escalations")
+    };
+
+    if escalations.is_empty() {
+        ctx.say("Warnings don't escalate yet!").await?;
+        return Ok(());
+    }
+
+    let lines = escalations
+        .iter()
+        .map(|e| format!("{} active warnings: {}", e.warning_count, describe_escalation(e)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    ctx.say(lines).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_due_escalation() {
+        let escalation = |count: i32| WarningEscalation {
+            escalation_id: count,
+            guild_id: 1,
+            warning_count: count,
+            action: "timeout".to_string(),
+            timeout_secs: 60,
+        };
+        let escalations = || vec![escalation(3), escalation(5)];
+        let due = |active: i64, applied: &[i32]| due_escalation(escalations(), active, applied).map(|e| e.warning_count);
+
+        assert_eq!(due(2, &[]), None);
+        assert_eq!(due(3, &[]), Some(3));
+        assert_eq!(due(4, &[3]), None);
+        // a count skipped by two warnings at once, or an escalation added later, still applies
+        assert_eq!(due(4, &[]), Some(3));
+        assert_eq!(due(6, &[3]), Some(5));
+        assert_eq!(due(6, &[5]), None);
+    }
+}
@@ -0,0 +1,33 @@
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+
+use crate::models::{GuildSettings, NewGuildSettings};
+
+/// Make sure a guild has a settings row, so it can be updated.
+pub fn ensure_guild_settings(conn: &SqliteConnection, of_guild: i64) {
+    use crate::schema::guild_settings;
+
+    diesel::insert_or_ignore_into(guild_settings::table)
+        .values(&NewGuildSettings { guild_id: of_guild })
+        .execute(conn)
+        .expect("Error creating guild settings");
+}
+
+/// The settings of a guild, the defaults if nothing has been configured yet.
+pub fn get_guild_settings(conn: &SqliteConnection, of_guild: i64) -> GuildSettings {
+    use crate::schema::guild_settings::dsl::*;
+
+    ensure_guild_settings(conn, of_guild);
+    guild_settings
+        .filter(guild_id.eq(of_guild))
+        .first(conn)
+        .expect("Error loading guild settings")
+}
+
+pub fn get_all_guild_settings(conn: &SqliteConnection) -> Vec<GuildSettings> {
+    use crate::schema::guild_settings::dsl::*;
+
+    guild_settings
+        .load(conn)
+        .expect("Error loading guild settings")
+}
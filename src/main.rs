@@ -6,23 +6,43 @@ extern crate log;
 extern crate lazy_static;
 
 mod bot_modules;
+mod guild_settings;
 mod models;
+mod pagination;
 mod schema;
 
+use std::collections::HashMap;
 use std::env;
 
 use diesel::{r2d2::ConnectionManager, SqliteConnection, connection::SimpleConnection};
 use dotenv::dotenv;
 use poise::{serenity_prelude::{self as serenity, GatewayIntents}, PrefixFrameworkOptions};
+use tokio::sync::{Mutex, RwLock};
 
 pub struct UserData {
     pub pool: r2d2::Pool<ConnectionManager<SqliteConnection>>,
+    pub autoresponses: RwLock<Vec<bot_modules::autoresponder::Trigger>>,
+    pub autoresponse_cooldowns: Mutex<bot_modules::autoresponder::Cooldowns>,
+    pub autoresponder_channel_rules: RwLock<Vec<models::AutoresponderChannelRule>>,
+    pub automod: RwLock<HashMap<serenity::GuildId, bot_modules::automod::AutomodConfig>>,
+    pub antispam: RwLock<HashMap<serenity::GuildId, bot_modules::antispam::AntispamConfig>>,
+    pub spam_tracker: Mutex<bot_modules::antispam::SpamTracker>,
+    pub raid_protection: RwLock<HashMap<serenity::GuildId, bot_modules::raid::RaidConfig>>,
+    pub join_tracker: Mutex<bot_modules::raid::JoinTracker>,
+    pub nickname_filter: RwLock<HashMap<serenity::GuildId, bot_modules::nicknames::NicknameFilterConfig>>,
+    pub log_channel_id: Option<serenity::ChannelId>,
+    pub message_cache: Mutex<bot_modules::modlog::MessageCache>,
+    pub modmail_channel_id: Option<serenity::ChannelId>,
+    pub appeal_channel_id: Option<serenity::ChannelId>,
 }
 
 type Data = UserData;
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, UserData, Error>;
 
+/// What prefix commands start with.
+const PREFIX: &str = "!";
+
 async fn register_all_commands(guild_id: u64, ctx: &serenity::Context, framework: &poise::FrameworkContext<'_, Data, Error>) -> Result<usize, serenity::Error> {
     let mut commands_builder = serenity::CreateApplicationCommands::default();
     let commands = &framework.options().commands;
@@ -43,7 +63,7 @@ async fn on_event(
     ctx: &serenity::Context,
     event: &poise::Event<'_>,
     framework: poise::FrameworkContext<'_, Data, Error>,
-    _user_data: &Data,
+    user_data: &Data,
 ) -> Result<(), Error> {
     match event {
         poise::Event::Ready { data_about_bot: _ } => {
@@ -56,16 +76,44 @@ async fn on_event(
                 info!("Registered {} commands for guild {}", registered_amount, g.0);
             }
             info!("Commands registered! Have fun!");
+            bot_modules::autoresponder::convert_moderator_only(ctx, user_data).await;
             ctx.set_activity(serenity::Activity::playing(format!("Becbot Reloaded v{}", env!("CARGO_PKG_VERSION")))).await;
         },
         poise::Event::Message {new_message} => {
-            let _ = bot_modules::autoresponder::handle_message(ctx, &framework, new_message).await;
-            
-            let log_channel_id = std::env::var("LOG_CHANNEL_ID");
-            if log_channel_id.is_err() {
-                warn!("LOG_CHANNEL_ID not set! Moderation module disabled...");
+            bot_modules::modlog::cache_message(ctx, user_data, new_message).await;
+            if bot_modules::appeals::handle_message(ctx, user_data, new_message).await {
+                return Ok(());
+            }
+            if bot_modules::modmail::handle_message(ctx, user_data, new_message).await {
+                return Ok(());
+            }
+            if bot_modules::antispam::handle_message(ctx, user_data, new_message).await {
+                return Ok(());
+            }
+            if bot_modules::automod::handle_message(ctx, user_data, new_message).await {
                 return Ok(());
             }
+            let _ = bot_modules::autoresponder::handle_message(ctx, &framework, new_message).await;
+        },
+        poise::Event::MessageUpdate { event, .. } => {
+            bot_modules::modlog::handle_message_update(ctx, user_data, event).await;
+        },
+        poise::Event::MessageDelete { channel_id, deleted_message_id, guild_id } => {
+            bot_modules::modlog::handle_message_delete(ctx, user_data, *channel_id, *deleted_message_id, *guild_id).await;
+        },
+        poise::Event::MessageDeleteBulk { channel_id, multiple_deleted_messages_ids, guild_id } => {
+            bot_modules::modlog::handle_message_delete_bulk(ctx, user_data, *channel_id, multiple_deleted_messages_ids, *guild_id).await;
+        },
+        poise::Event::GuildMemberAddition { new_member } => {
+            if !bot_modules::raid::handle_member_addition(ctx, user_data, new_member).await {
+                bot_modules::nicknames::check_member(ctx, user_data, new_member).await;
+            }
+        },
+        poise::Event::GuildMemberUpdate { old_if_available, new } => {
+            bot_modules::nicknames::handle_member_update(ctx, user_data, old_if_available.as_ref(), new).await;
+        },
+        poise::Event::InteractionCreate { interaction } => {
+            bot_modules::appeals::handle_interaction(ctx, user_data, interaction).await?;
         },
         poise::Event::ReactionAdd { add_reaction } => {
             bot_modules::suggestions::handle_reaction_add(ctx, framework, add_reaction).await?;
@@ -93,10 +141,10 @@ async fn main() {
         env::var("DATABASE_URL").expect("Expected a database url in the environment");
 
     let framework = poise::Framework::builder()
-        .intents(serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::MESSAGE_CONTENT)
+        .intents(serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::MESSAGE_CONTENT | serenity::GatewayIntents::GUILD_MEMBERS)
         .token(token)
         .user_data_setup(
-            move |_ctx, _ready, _framework: &poise::Framework<UserData, Error>| {
+            move |ctx, _ready, _framework: &poise::Framework<UserData, Error>| {
                 Box::pin(async move {
                     let manager = ConnectionManager::<SqliteConnection>::new(database_url);
                     let pool = r2d2::Pool::builder()
@@ -113,8 +161,53 @@ async fn main() {
                         PRAGMA busy_timeout = 5000;         -- sleep if the database is busy
                         PRAGMA foreign_keys = ON;           -- enforce foreign keys
                     ").unwrap();
+
+                    if let Some(path) = env::var("AUTORESPONSES_FILE").ok().filter(|p| !p.is_empty()) {
+                        match bot_modules::autoresponder::import_export::load_file(&conn, &path) {
+                            Ok(Some(added)) => info!("Added {} autoresponses from {}", added, path),
+                            Ok(None) => info!("Not loading {}, there already are autoresponses", path),
+                            Err(e) => error!("Couldn't load autoresponses from {}: {}", path, e),
+                        }
+                    }
+
+                    let autoresponses = bot_modules::autoresponder::load_triggers(&conn);
+                    let autoresponder_channel_rules = bot_modules::autoresponder::load_guild_channel_rules(&conn);
+                    info!("Loaded {} autoresponses", autoresponses.len());
+                    let automod = bot_modules::automod::load_automod_configs(&conn);
+                    let antispam = bot_modules::antispam::load_antispam_configs(&conn);
+                    let raid_protection = bot_modules::raid::load_raid_configs(&conn);
+                    let nickname_filter = bot_modules::nicknames::load_nickname_filter_configs(&conn);
+
+                    let log_channel_id = env::var("LOG_CHANNEL_ID").ok().and_then(|id| id.parse().ok()).map(serenity::ChannelId);
+                    if log_channel_id.is_none() {
+                        warn!("LOG_CHANNEL_ID not set! Moderation log disabled...");
+                    }
+                    let modmail_channel_id = env::var("MODMAIL_CHANNEL_ID").ok().and_then(|id| id.parse().ok()).map(serenity::ChannelId);
+                    if modmail_channel_id.is_none() {
+                        warn!("MODMAIL_CHANNEL_ID not set! Modmail disabled...");
+                    }
+                    let appeal_channel_id = env::var("APPEAL_CHANNEL_ID").ok().and_then(|id| id.parse().ok()).map(serenity::ChannelId);
+                    if appeal_channel_id.is_none() {
+                        warn!("APPEAL_CHANNEL_ID not set! Ban appeals disabled...");
+                    }
+                    bot_modules::moderation::start_tempban_scheduler(ctx.clone(), pool.clone(), log_channel_id);
                     
-                    Ok(UserData { pool })
+                    Ok(UserData {
+                        pool,
+                        autoresponses: RwLock::new(autoresponses),
+                        autoresponse_cooldowns: Mutex::new(Default::default()),
+                        autoresponder_channel_rules: RwLock::new(autoresponder_channel_rules),
+                        automod: RwLock::new(automod),
+                        antispam: RwLock::new(antispam),
+                        spam_tracker: Mutex::new(Default::default()),
+                        raid_protection: RwLock::new(raid_protection),
+                        join_tracker: Mutex::new(Default::default()),
+                        nickname_filter: RwLock::new(nickname_filter),
+                        log_channel_id,
+                        message_cache: Mutex::new(Default::default()),
+                        modmail_channel_id,
+                        appeal_channel_id,
+                    })
                 })
             },
         )
@@ -148,11 +241,33 @@ async fn main() {
                 bot_modules::misc::worldbuilding(),
                 bot_modules::colors::color(),
                 bot_modules::colors::clearcolor(),
+                bot_modules::colors::colorcontrast(),
                 bot_modules::suggestions::suggest(),
-                bot_modules::suggestions::suggest_message()
+                bot_modules::suggestions::suggest_message(),
+                bot_modules::autoresponder::autoresponse(),
+                bot_modules::automod::automod(),
+                bot_modules::antispam::antispam(),
+                bot_modules::raid::raid(),
+                bot_modules::raid::lockdown(),
+                bot_modules::nicknames::nickname_filter(),
+                bot_modules::warnings::warn(),
+                bot_modules::warnings::warnings(),
+                bot_modules::warnings::unwarn(),
+                bot_modules::warnings::escalation(),
+                bot_modules::moderation::timeout(),
+                bot_modules::moderation::kick(),
+                bot_modules::moderation::ban(),
+                bot_modules::moderation::tempban(),
+                bot_modules::moderation::case(),
+                bot_modules::moderation::cases(),
+                bot_modules::notes::note(),
+                bot_modules::notes::userinfo(),
+                bot_modules::modmail::modmail(),
+                bot_modules::appeals::banappeal(),
+                bot_modules::purge::purge(),
             ],
             prefix_options: PrefixFrameworkOptions {
-                prefix: Some("!".into()),
+                prefix: Some(PREFIX.into()),
                 ..Default::default()
             },
             ..Default::default()
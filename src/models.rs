@@ -1,5 +1,9 @@
-use super::schema::{cans, quotes, suggestions};
-use chrono::NaiveDateTime;
+use super::schema::{
+    appeal_votes, automod_exemptions, autoresponder_channel_rules, autoresponse_responses, autoresponse_stats,
+    autoresponses, ban_appeals, cans, guild_settings, mod_cases, modmail_messages, modmail_tickets, nickname_patterns,
+    notes, quotes, suggestions, warning_escalations, warnings,
+};
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::Insertable;
 
 #[derive(Queryable)]
@@ -52,4 +56,371 @@ pub struct NewSuggestion<'a> {
     pub suggestion_date: NaiveDateTime,
     pub suggestion_author_id: i64,
     pub suggestion_message_id: i64,
+}
+
+#[derive(Queryable, Clone)]
+pub struct Autoresponse {
+    pub autoresponse_id: i32,
+    pub pattern: String,
+    pub match_kind: String,
+    pub case_sensitive: bool,
+    pub moderator_only: bool,
+    pub enabled: bool,
+    pub channel_cooldown_secs: i32,
+    pub user_cooldown_secs: i32,
+    pub chance_percent: i32,
+    pub trigger_count: i32,
+    pub allowed_role_ids: String,
+    pub response_kind: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "autoresponses"]
+pub struct NewAutoresponse<'a> {
+    pub pattern: &'a str,
+    pub match_kind: &'a str,
+    pub case_sensitive: bool,
+    pub channel_cooldown_secs: i32,
+    pub user_cooldown_secs: i32,
+    pub chance_percent: i32,
+    pub allowed_role_ids: &'a str,
+    pub response_kind: &'a str,
+}
+
+#[derive(AsChangeset, Default)]
+#[table_name = "autoresponses"]
+pub struct AutoresponseChanges<'a> {
+    pub pattern: Option<&'a str>,
+    pub match_kind: Option<&'a str>,
+    pub case_sensitive: Option<bool>,
+    pub channel_cooldown_secs: Option<i32>,
+    pub user_cooldown_secs: Option<i32>,
+    pub chance_percent: Option<i32>,
+    pub response_kind: Option<&'a str>,
+}
+
+#[derive(Queryable, Clone)]
+pub struct AutoresponseResponse {
+    pub response_id: i32,
+    pub autoresponse_id: i32,
+    pub response: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "autoresponse_responses"]
+pub struct NewAutoresponseResponse<'a> {
+    pub autoresponse_id: i32,
+    pub response: &'a str,
+}
+
+#[derive(Queryable, Clone)]
+pub struct AutoresponderChannelRule {
+    pub rule_id: i32,
+    pub guild_id: i64,
+    pub autoresponse_id: Option<i32>,
+    pub channel_id: i64,
+    pub allow: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "autoresponder_channel_rules"]
+pub struct NewAutoresponderChannelRule {
+    pub guild_id: i64,
+    pub autoresponse_id: Option<i32>,
+    pub channel_id: i64,
+    pub allow: bool,
+}
+
+#[derive(Queryable, Insertable)]
+#[table_name = "autoresponse_stats"]
+pub struct AutoresponseStat {
+    pub autoresponse_id: i32,
+    pub guild_id: i64,
+    pub channel_id: i64,
+    pub day: NaiveDate,
+    pub count: i32,
+}
+
+#[derive(Queryable, Clone)]
+pub struct GuildSettings {
+    pub guild_id: i64,
+    pub automod_enabled: bool,
+    pub automod_profanity: String,
+    pub automod_offensive: String,
+    pub automod_sexual: String,
+    pub automod_mean: String,
+    pub automod_action: String,
+    pub automod_timeout_secs: i32,
+    pub antispam_enabled: bool,
+    pub antispam_window_secs: i32,
+    pub antispam_max_messages: i32,
+    pub antispam_max_duplicates: i32,
+    pub antispam_max_mentions: i32,
+    pub antispam_action: String,
+    pub antispam_timeout_secs: i32,
+    pub raid_enabled: bool,
+    pub raid_window_secs: i32,
+    pub raid_max_joins: i32,
+    pub raid_max_suspicious: i32,
+    pub raid_new_account_secs: i32,
+    pub raid_lockdown: String,
+    pub lockdown_mode: Option<String>,
+    pub lockdown_previous_verification: Option<i32>,
+    pub nickname_filter_enabled: bool,
+    pub nickname_filter_severity: String,
+    pub nickname_filter_action: String,
+    pub nickname_replacement: String,
+    pub color_contrast_mode: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "guild_settings"]
+pub struct NewGuildSettings {
+    pub guild_id: i64,
+}
+
+#[derive(AsChangeset, Default)]
+#[table_name = "guild_settings"]
+pub struct AutomodSettingsChanges<'a> {
+    pub automod_enabled: Option<bool>,
+    pub automod_profanity: Option<&'a str>,
+    pub automod_offensive: Option<&'a str>,
+    pub automod_sexual: Option<&'a str>,
+    pub automod_mean: Option<&'a str>,
+    pub automod_action: Option<&'a str>,
+    pub automod_timeout_secs: Option<i32>,
+}
+
+#[derive(AsChangeset, Default)]
+#[table_name = "guild_settings"]
+pub struct AntispamSettingsChanges<'a> {
+    pub antispam_enabled: Option<bool>,
+    pub antispam_window_secs: Option<i32>,
+    pub antispam_max_messages: Option<i32>,
+    pub antispam_max_duplicates: Option<i32>,
+    pub antispam_max_mentions: Option<i32>,
+    pub antispam_action: Option<&'a str>,
+    pub antispam_timeout_secs: Option<i32>,
+}
+
+#[derive(AsChangeset, Default)]
+#[table_name = "guild_settings"]
+pub struct RaidSettingsChanges<'a> {
+    pub raid_enabled: Option<bool>,
+    pub raid_window_secs: Option<i32>,
+    pub raid_max_joins: Option<i32>,
+    pub raid_max_suspicious: Option<i32>,
+    pub raid_new_account_secs: Option<i32>,
+    pub raid_lockdown: Option<&'a str>,
+    pub lockdown_mode: Option<Option<&'a str>>,
+    pub lockdown_previous_verification: Option<Option<i32>>,
+}
+
+#[derive(AsChangeset, Default)]
+#[table_name = "guild_settings"]
+pub struct NicknameFilterSettingsChanges<'a> {
+    pub nickname_filter_enabled: Option<bool>,
+    pub nickname_filter_severity: Option<&'a str>,
+    pub nickname_filter_action: Option<&'a str>,
+    pub nickname_replacement: Option<&'a str>,
+}
+
+#[derive(AsChangeset, Default)]
+#[table_name = "guild_settings"]
+pub struct ColorSettingsChanges<'a> {
+    pub color_contrast_mode: Option<&'a str>,
+}
+
+#[derive(Queryable, Clone)]
+pub struct AutomodExemption {
+    pub exemption_id: i32,
+    pub guild_id: i64,
+    pub target_id: i64,
+    pub is_role: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "automod_exemptions"]
+pub struct NewAutomodExemption {
+    pub guild_id: i64,
+    pub target_id: i64,
+    pub is_role: bool,
+}
+
+#[derive(Queryable, Clone)]
+pub struct Warning {
+    pub warning_id: i32,
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub moderator_id: i64,
+    pub reason: String,
+    pub created_at: NaiveDateTime,
+    pub active: bool,
+    pub escalation_count: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[table_name = "warnings"]
+pub struct NewWarning<'a> {
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub moderator_id: i64,
+    pub reason: &'a str,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Clone)]
+pub struct WarningEscalation {
+    pub escalation_id: i32,
+    pub guild_id: i64,
+    pub warning_count: i32,
+    pub action: String,
+    pub timeout_secs: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "warning_escalations"]
+pub struct NewWarningEscalation<'a> {
+    pub guild_id: i64,
+    pub warning_count: i32,
+    pub action: &'a str,
+    pub timeout_secs: i32,
+}
+
+#[derive(Queryable, Clone)]
+pub struct ModCase {
+    pub case_id: i32,
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub moderator_id: i64,
+    pub action: String,
+    pub reason: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub lifted: bool,
+    pub log_channel_id: Option<i64>,
+    pub log_message_id: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name = "mod_cases"]
+pub struct NewModCase<'a> {
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub moderator_id: i64,
+    pub action: &'a str,
+    pub reason: &'a str,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Clone)]
+pub struct Note {
+    pub note_id: i32,
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub moderator_id: i64,
+    pub content: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "notes"]
+pub struct NewNote<'a> {
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub moderator_id: i64,
+    pub content: &'a str,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Clone)]
+pub struct ModmailTicket {
+    pub ticket_id: i32,
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub thread_id: i64,
+    pub opened_at: NaiveDateTime,
+    pub closed_at: Option<NaiveDateTime>,
+    pub closed_by: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name = "modmail_tickets"]
+pub struct NewModmailTicket {
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub thread_id: i64,
+    pub opened_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Clone)]
+pub struct ModmailMessage {
+    pub modmail_message_id: i32,
+    pub ticket_id: i32,
+    pub author_id: i64,
+    pub author_tag: String,
+    pub from_staff: bool,
+    pub content: String,
+    pub attachments: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "modmail_messages"]
+pub struct NewModmailMessage<'a> {
+    pub ticket_id: i32,
+    pub author_id: i64,
+    pub author_tag: &'a str,
+    pub from_staff: bool,
+    pub content: &'a str,
+    pub attachments: &'a str,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Clone)]
+pub struct BanAppeal {
+    pub appeal_id: i32,
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub case_id: i32,
+    pub content: String,
+    pub created_at: NaiveDateTime,
+    pub status: String,
+    pub decided_by: Option<i64>,
+    pub decided_at: Option<NaiveDateTime>,
+    pub staff_channel_id: Option<i64>,
+    pub staff_message_id: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name = "ban_appeals"]
+pub struct NewBanAppeal<'a> {
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub case_id: i32,
+    pub content: &'a str,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Insertable)]
+#[table_name = "appeal_votes"]
+pub struct AppealVote {
+    pub appeal_id: i32,
+    pub voter_id: i64,
+    pub approve: bool,
+}
+
+#[derive(Queryable, Clone)]
+pub struct NicknamePattern {
+    pub pattern_id: i32,
+    pub guild_id: i64,
+    pub pattern: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "nickname_patterns"]
+pub struct NewNicknamePattern<'a> {
+    pub guild_id: i64,
+    pub pattern: &'a str,
 }
\ No newline at end of file
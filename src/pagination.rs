@@ -0,0 +1,82 @@
+use poise::serenity_prelude as serenity;
+
+use crate::{Context, Error};
+
+/// Send an embed with the given pages, which can be flipped through with buttons for two minutes.
+///
+/// Ephemeral pages are only shown to whoever used the command.
+pub async fn paginate(ctx: Context<'_>, title: &str, pages: &[String], ephemeral: bool) -> Result<(), Error> {
+    if pages.is_empty() {
+        return Ok(());
+    }
+
+    let ctx_id = ctx.id();
+    let prev_button_id = format!("{}prev", ctx_id);
+    let next_button_id = format!("{}next", ctx_id);
+
+    let mut current_page = 0;
+    let reply = ctx.send(|cr| {
+        cr.ephemeral(ephemeral);
+        cr.embed(|ce| ce
+            .title(title)
+            .description(&pages[current_page])
+            .footer(|f| f.text(format!("Page {}/{}", current_page + 1, pages.len())))
+        );
+        if pages.len() > 1 {
+            cr.components(|cc| cc
+                .create_action_row(|car| car
+                    .create_button(|cb| cb
+                        .custom_id(&prev_button_id)
+                        .emoji(serenity::ReactionType::Unicode("◀️".to_string()))
+                    )
+                    .create_button(|cb| cb
+                        .custom_id(&next_button_id)
+                        .emoji(serenity::ReactionType::Unicode("▶️".to_string()))
+                    )
+                )
+            );
+        }
+        cr
+    }).await?;
+
+    if pages.len() == 1 {
+        return Ok(());
+    }
+
+    while let Some(press) = serenity::CollectComponentInteraction::new(ctx.discord())
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(std::time::Duration::from_secs(120))
+        .collect_limit(1)
+        .await
+    {
+        if press.data.custom_id == next_button_id {
+            current_page = (current_page + 1) % pages.len();
+        } else if press.data.custom_id == prev_button_id {
+            current_page = current_page.checked_sub(1).unwrap_or(pages.len() - 1);
+        } else {
+            continue;
+        }
+
+        press.create_interaction_response(ctx.discord(), |ir| ir
+            .kind(serenity::InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|ird| ird
+                .embed(|ce| ce
+                    .title(title)
+                    .description(&pages[current_page])
+                    .footer(|f| f.text(format!("Page {}/{}", current_page + 1, pages.len())))
+                )
+            )
+        ).await?;
+    }
+
+    reply.edit(ctx, |m| m
+        .embed(|ce| ce
+            .title(title)
+            .description(&pages[current_page])
+            .footer(|f| f.text(format!("Page {}/{}", current_page + 1, pages.len())))
+        )
+        .components(|c| c)
+    ).await?;
+
+    Ok(())
+}
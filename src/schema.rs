@@ -1,3 +1,81 @@
+table! {
+    appeal_votes (appeal_id, voter_id) {
+        appeal_id -> Integer,
+        voter_id -> BigInt,
+        approve -> Bool,
+    }
+}
+
+table! {
+    automod_exemptions (exemption_id) {
+        exemption_id -> Integer,
+        guild_id -> BigInt,
+        target_id -> BigInt,
+        is_role -> Bool,
+    }
+}
+
+table! {
+    autoresponse_responses (response_id) {
+        response_id -> Integer,
+        autoresponse_id -> Integer,
+        response -> Text,
+    }
+}
+
+table! {
+    autoresponder_channel_rules (rule_id) {
+        rule_id -> Integer,
+        guild_id -> BigInt,
+        autoresponse_id -> Nullable<Integer>,
+        channel_id -> BigInt,
+        allow -> Bool,
+    }
+}
+
+table! {
+    autoresponse_stats (autoresponse_id, guild_id, channel_id, day) {
+        autoresponse_id -> Integer,
+        guild_id -> BigInt,
+        channel_id -> BigInt,
+        day -> Date,
+        count -> Integer,
+    }
+}
+
+table! {
+    autoresponses (autoresponse_id) {
+        autoresponse_id -> Integer,
+        pattern -> Text,
+        match_kind -> Text,
+        case_sensitive -> Bool,
+        moderator_only -> Bool,
+        enabled -> Bool,
+        channel_cooldown_secs -> Integer,
+        user_cooldown_secs -> Integer,
+        chance_percent -> Integer,
+        trigger_count -> Integer,
+        allowed_role_ids -> Text,
+        response_kind -> Text,
+    }
+}
+
+table! {
+    ban_appeals (appeal_id) {
+        appeal_id -> Integer,
+        guild_id -> BigInt,
+        user_id -> BigInt,
+        case_id -> Integer,
+        content -> Text,
+        created_at -> Timestamp,
+        status -> Text,
+        decided_by -> Nullable<BigInt>,
+        decided_at -> Nullable<Timestamp>,
+        staff_channel_id -> Nullable<BigInt>,
+        staff_message_id -> Nullable<BigInt>,
+    }
+}
+
 table! {
     cans (can_id) {
         can_id -> Integer,
@@ -7,6 +85,99 @@ table! {
     }
 }
 
+table! {
+    guild_settings (guild_id) {
+        guild_id -> BigInt,
+        automod_enabled -> Bool,
+        automod_profanity -> Text,
+        automod_offensive -> Text,
+        automod_sexual -> Text,
+        automod_mean -> Text,
+        automod_action -> Text,
+        automod_timeout_secs -> Integer,
+        antispam_enabled -> Bool,
+        antispam_window_secs -> Integer,
+        antispam_max_messages -> Integer,
+        antispam_max_duplicates -> Integer,
+        antispam_max_mentions -> Integer,
+        antispam_action -> Text,
+        antispam_timeout_secs -> Integer,
+        raid_enabled -> Bool,
+        raid_window_secs -> Integer,
+        raid_max_joins -> Integer,
+        raid_max_suspicious -> Integer,
+        raid_new_account_secs -> Integer,
+        raid_lockdown -> Text,
+        lockdown_mode -> Nullable<Text>,
+        lockdown_previous_verification -> Nullable<Integer>,
+        nickname_filter_enabled -> Bool,
+        nickname_filter_severity -> Text,
+        nickname_filter_action -> Text,
+        nickname_replacement -> Text,
+        color_contrast_mode -> Text,
+    }
+}
+
+table! {
+    mod_cases (case_id) {
+        case_id -> Integer,
+        guild_id -> BigInt,
+        user_id -> BigInt,
+        moderator_id -> BigInt,
+        action -> Text,
+        reason -> Text,
+        created_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+        lifted -> Bool,
+        log_channel_id -> Nullable<BigInt>,
+        log_message_id -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    modmail_messages (modmail_message_id) {
+        modmail_message_id -> Integer,
+        ticket_id -> Integer,
+        author_id -> BigInt,
+        author_tag -> Text,
+        from_staff -> Bool,
+        content -> Text,
+        attachments -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    modmail_tickets (ticket_id) {
+        ticket_id -> Integer,
+        guild_id -> BigInt,
+        user_id -> BigInt,
+        thread_id -> BigInt,
+        opened_at -> Timestamp,
+        closed_at -> Nullable<Timestamp>,
+        closed_by -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    nickname_patterns (pattern_id) {
+        pattern_id -> Integer,
+        guild_id -> BigInt,
+        pattern -> Text,
+    }
+}
+
+table! {
+    notes (note_id) {
+        note_id -> Integer,
+        guild_id -> BigInt,
+        user_id -> BigInt,
+        moderator_id -> BigInt,
+        content -> Text,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     quotes (quote_id) {
         quote_id -> Integer,
@@ -27,8 +198,53 @@ table! {
     }
 }
 
+table! {
+    warning_escalations (escalation_id) {
+        escalation_id -> Integer,
+        guild_id -> BigInt,
+        warning_count -> Integer,
+        action -> Text,
+        timeout_secs -> Integer,
+    }
+}
+
+table! {
+    warnings (warning_id) {
+        warning_id -> Integer,
+        guild_id -> BigInt,
+        user_id -> BigInt,
+        moderator_id -> BigInt,
+        reason -> Text,
+        created_at -> Timestamp,
+        active -> Bool,
+        escalation_count -> Nullable<Integer>,
+    }
+}
+
+joinable!(appeal_votes -> ban_appeals (appeal_id));
+joinable!(autoresponse_responses -> autoresponses (autoresponse_id));
+joinable!(autoresponse_stats -> autoresponses (autoresponse_id));
+joinable!(autoresponder_channel_rules -> autoresponses (autoresponse_id));
+joinable!(ban_appeals -> mod_cases (case_id));
+joinable!(modmail_messages -> modmail_tickets (ticket_id));
+
 allow_tables_to_appear_in_same_query!(
+    appeal_votes,
+    automod_exemptions,
+    autoresponse_responses,
+    autoresponse_stats,
+    autoresponder_channel_rules,
+    autoresponses,
+    ban_appeals,
     cans,
+    guild_settings,
+    mod_cases,
+    modmail_messages,
+    modmail_tickets,
+    nickname_patterns,
+    notes,
     quotes,
     suggestions,
+    warning_escalations,
+    warnings,
 );